@@ -26,6 +26,18 @@ struct Cli {
     /// Directory to put the project in. Defaults to `./output/`
     #[arg(short, long, value_name = "DIR")]
     output: Option<PathBuf>,
+    /// Don't draw the green flag and stop buttons over the stage. They can
+    /// still be used with F5 and F6.
+    #[arg(long)]
+    no_controls: bool,
+    /// Let sprites move off the stage, instead of always keeping part of
+    /// them visible.
+    #[arg(long)]
+    no_fencing: bool,
+    /// Copy the Scratch fonts from this directory instead of downloading
+    /// them.
+    #[arg(long, value_name = "DIR")]
+    fonts: Option<PathBuf>,
 }
 
 /// Parse a scratch id. This can either be a plain number,
@@ -72,22 +84,28 @@ fn parse_id(s: &str) -> Result<u64, ()> {
 /// ```
 fn make_blocks_lookup() -> HashMap<&'static str, &'static str> {
     let mut blocks: HashMap<&str, &str> = HashMap::new();
-    blocks.insert("motion_setx", "set_x(sprite.clone().unwrap(),X);");
-    blocks.insert("motion_sety", "set_y(sprite.clone().unwrap(),Y);");
+    blocks.insert(
+        "motion_setx",
+        "set_x(sprite.clone().unwrap(),stage.clone(),X);",
+    );
+    blocks.insert(
+        "motion_sety",
+        "set_y(sprite.clone().unwrap(),stage.clone(),Y);",
+    );
     blocks.insert(
         "motion_changexby",
-        "change_x_by(sprite.clone().unwrap(),DX);",
+        "change_x_by(sprite.clone().unwrap(),stage.clone(),DX);",
     );
     blocks.insert(
         "motion_changeyby",
-        "change_y_by(sprite.clone().unwrap(),DY);",
+        "change_y_by(sprite.clone().unwrap(),stage.clone(),DY);",
     );
     blocks.insert("motion_xposition", "get_x(sprite.clone().unwrap())");
     blocks.insert("motion_yposition", "get_y(sprite.clone().unwrap())");
     // blocks.insert("motion_movesteps", "object.move_steps(STEPSf32);");
     blocks.insert(
         "motion_movesteps",
-        "move_steps(sprite.clone().unwrap(),STEPS);",
+        "move_steps(sprite.clone().unwrap(),stage.clone(),STEPS);",
     );
     blocks.insert(
         "motion_turnleft",
@@ -101,18 +119,37 @@ fn make_blocks_lookup() -> HashMap<&'static str, &'static str> {
         "motion_goto",
         "go_to(sprite.clone().unwrap(),stage.clone(),TO);",
     );
-    blocks.insert("motion_gotoxy", "go_to_xy(sprite.clone().unwrap(),X,Y);");
+    blocks.insert(
+        "motion_gotoxy",
+        "go_to_xy(sprite.clone().unwrap(),stage.clone(),X,Y);",
+    );
+    blocks.insert(
+        "motion_ifonedgebounce",
+        "if_on_edge_bounce(sprite.clone().unwrap(),stage.clone());",
+    );
+    blocks.insert(
+        "motion_glidesecstoxy",
+        "glide_secs_to_xy(sprite.clone().unwrap(),stage.clone(),SECS,X,Y).await;",
+    );
+    blocks.insert(
+        "motion_glideto",
+        "glide_to(sprite.clone().unwrap(),stage.clone(),SECS,TO).await;",
+    );
     blocks.insert(
         "motion_pointindirection",
         "point_in_direction(sprite.clone().unwrap(),DIRECTION);",
     );
+    blocks.insert(
+        "motion_pointtowards",
+        "point_towards(sprite.clone().unwrap(),stage.clone(),TOWARDS);",
+    );
     blocks.insert(
         "motion_setrotationstyle",
         "set_rotation_style(sprite.clone().unwrap(),STYLE);",
     );
     blocks.insert(
         "sound_playuntildone",
-        "Wait::new(Duration::from_secs_f32(play_sound(sprite.clone(), stage.clone(), SOUND_MENU))).await;",
+        "wait(stage.clone(), Value::from(play_sound(sprite.clone(), stage.clone(), SOUND_MENU))).await;",
     );
     blocks.insert(
         "sound_play",
@@ -133,10 +170,7 @@ fn make_blocks_lookup() -> HashMap<&'static str, &'static str> {
         "control_repeat",
         "for z in 0..TIMES.into(){SUBSTACK\nYield::Start.await;}",
     );
-    blocks.insert(
-        "control_wait",
-        "Wait::new(Duration::from_secs(DURATION.into())).await;",
-    );
+    blocks.insert("control_wait", "wait(stage.clone(), DURATION).await;");
     blocks.insert("control_forever", "loop{SUBSTACK\nYield::Start.await;}");
     blocks.insert(
         "control_if",
@@ -162,12 +196,10 @@ fn make_blocks_lookup() -> HashMap<&'static str, &'static str> {
     );
     blocks.insert(
         "control_create_clone_of",
-        "{
-            let index = stage.lock().unwrap().sprites.len();
-            let sprite_name = create_clone(stage.clone(),sprite.clone().unwrap(),CLONE_OPTION);
-            let mut new_threads = clone_sprite(sprite_name.clone(),stage.lock().unwrap().sprites[index].clone(),stage.clone());
+        "if let Some((sprite_name, clone)) = create_clone(stage.clone(),sprite.clone(),CLONE_OPTION){
+            let mut new_threads = clone_sprite(sprite_name.clone(),clone,stage.clone());
             for thread in &mut new_threads{
-                if thread.start == StartType::StartAsClone(format!(\"{}_clone\",sprite_name.clone())){
+                if thread.start == StartType::StartAsClone(sprite_name.clone()){
                     thread.running=true;
                 }
             }
@@ -176,17 +208,25 @@ fn make_blocks_lookup() -> HashMap<&'static str, &'static str> {
     );
     blocks.insert(
         "control_stop",
-        "if stop(sprite.clone(), stage.clone(), thread_uuid, Value::from(STOP_OPTION)) == StopType::ThisScript{return;}",
+        "if matches!(stop(sprite.clone(), stage.clone(), thread_uuid, Value::from(STOP_OPTION)), StopType::All | StopType::ThisScript){return;}",
     );
     blocks.insert("control_create_clone_of_menu", "Value::from(CLONE_OPTION)");
     blocks.insert("control_start_as_clone", "");
     blocks.insert(
         "control_delete_this_clone",
-        "delete_this_clone(stage.clone(),sprite.clone().unwrap());",
+        "if delete_this_clone(stage.clone(),sprite.clone().unwrap()){return;}",
     );
 
-    // blocks.insert("looks_say", "object.say(String::from(\"MESSAGE\"));");
-    blocks.insert("looks_say", "say(MESSAGE);");
+    blocks.insert("looks_say", "say(sprite.clone().unwrap(),MESSAGE);");
+    blocks.insert("looks_think", "think(sprite.clone().unwrap(),MESSAGE);");
+    blocks.insert(
+        "looks_sayforsecs",
+        "say_for_secs(sprite.clone().unwrap(),stage.clone(),MESSAGE,SECS).await;",
+    );
+    blocks.insert(
+        "looks_thinkforsecs",
+        "think_for_secs(sprite.clone().unwrap(),stage.clone(),MESSAGE,SECS).await;",
+    );
     blocks.insert(
         "looks_switchbackdropto",
         "switch_backdrop(stage.clone(),BACKDROP);",
@@ -239,6 +279,22 @@ fn make_blocks_lookup() -> HashMap<&'static str, &'static str> {
         "data_changevariableby",
         "change_variable(sprite.clone(),stage.clone(),VARIABLE,VALUE);",
     );
+    blocks.insert(
+        "data_showvariable",
+        "show_variable(sprite.clone(),stage.clone(),VARIABLE);",
+    );
+    blocks.insert(
+        "data_hidevariable",
+        "hide_variable(sprite.clone(),stage.clone(),VARIABLE);",
+    );
+    blocks.insert(
+        "data_showlist",
+        "show_list(sprite.clone(),stage.clone(),LIST);",
+    );
+    blocks.insert(
+        "data_hidelist",
+        "hide_list(sprite.clone(),stage.clone(),LIST);",
+    );
     blocks.insert(
         "data_addtolist",
         "add_to_list(sprite.clone(),stage.clone(),ITEM,LIST);",
@@ -307,13 +363,48 @@ fn make_blocks_lookup() -> HashMap<&'static str, &'static str> {
     blocks.insert("sensing_mousex", "mousex(stage.clone())");
     blocks.insert("sensing_mousey", "mousey(stage.clone())");
     blocks.insert("sensing_mousedown", "mouse_down(stage.clone())");
-    blocks.insert("sensing_askandwait", "ask(stage.clone(),QUESTION);");
+    blocks.insert(
+        "sensing_setdragmode",
+        "set_drag_mode(sprite.clone().unwrap(),DRAG_MODE);",
+    );
+    blocks.insert("sensing_timer", "timer(stage.clone())");
+    blocks.insert("sensing_resettimer", "reset_timer(stage.clone());");
+    blocks.insert(
+        "sensing_askandwait",
+        "ask(sprite.clone(),stage.clone(),QUESTION).await;",
+    );
     blocks.insert("sensing_answer", "answer(stage.clone())");
-    blocks.insert("sensing_username", "username()");
+    blocks.insert("sensing_username", "username(stage.clone())");
     blocks.insert("sensing_keyoptions", "Value::from(KEY_OPTION)");
     blocks.insert("motion_goto_menu", "Value::from(TO)");
+    blocks.insert("motion_glideto_menu", "Value::from(TO)");
+    blocks.insert("motion_pointtowards_menu", "Value::from(TOWARDS)");
+    blocks.insert(
+        "sensing_distanceto",
+        "distance_to(sprite.clone(),stage.clone(),DISTANCETOMENU)",
+    );
+    blocks.insert("sensing_distancetomenu", "Value::from(DISTANCETOMENU)");
+    blocks.insert(
+        "sensing_touchingobject",
+        "touching_object(sprite.clone().unwrap(),stage.clone(),TOUCHINGOBJECTMENU)",
+    );
+    blocks.insert(
+        "sensing_touchingobjectmenu",
+        "Value::from(TOUCHINGOBJECTMENU)",
+    );
+    blocks.insert("sensing_of", "attribute_of(stage.clone(),PROPERTY,OBJECT)");
+    blocks.insert("sensing_of_object_menu", "Value::from(OBJECT)");
+    blocks.insert(
+        "sensing_touchingcolor",
+        "touching_color(sprite.clone().unwrap(),stage.clone(),COLOR)",
+    );
+    blocks.insert(
+        "sensing_coloristouchingcolor",
+        "color_is_touching_color(sprite.clone().unwrap(),stage.clone(),COLOR,COLOR2)",
+    );
 
-    blocks.insert("sensing_dayssince2000", "days_since_2000()");
+    blocks.insert("sensing_dayssince2000", "days_since_2000(stage.clone())");
+    blocks.insert("sensing_current", "current_date(stage.clone(),CURRENTMENU)");
     blocks.insert("pen_stamp", "stamp(sprite.clone().unwrap(),stage.clone());");
     blocks.insert("pen_clear", "clear_pen(stage.clone());");
 
@@ -352,6 +443,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     };
 
     fs::write(readme, create_readme(&project_details)?)?;
+    create_config(&output)?;
 
     let filename = {
         let mut f = output.clone();
@@ -374,7 +466,12 @@ fn main() -> Result<(), Box<dyn Error>> {
             project["targets"].len(),
             target["name"]
         );
-        targets.push(generate_target(target, &block_reference)?);
+        targets.push(generate_target(
+            target,
+            &block_reference,
+            !cli.no_fencing,
+            &project["monitors"],
+        )?);
         get_target_assets(target, &output)?;
 
         target_clone_fns.push(format!(
@@ -383,6 +480,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         ));
     }
 
+    println!("Getting fonts...");
+    get_fonts(&output, cli.fonts.as_deref())?;
+
     let output = format!(
         "
         // This is the static Sprite, Stage, and block definitions
@@ -426,7 +526,8 @@ fn main() -> Result<(), Box<dyn Error>> {
 
             {{Stage.lock().unwrap().sprites.sort_by(|a,b| a.lock().unwrap().layer.cmp(&b.lock().unwrap().layer));}}
 
-            program.click_flag();
+            program.show_controls({show_controls});
+            program.click_flag(Stage.clone());
             'running: loop{{
                 program.tick(Stage.clone());
                 program.render(Stage.clone());
@@ -436,27 +537,15 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                     match event{{
                         Event::Quit {{..}} => {{break 'running;}},
-                        Event::KeyDown {{keycode: Some(key), ..}} => {{
-                            let mut s = Stage.lock().unwrap();
-                            s.keyboard.press_key(key);
-                        }},
-                        Event::KeyUp {{keycode: Some(key), ..}} => {{
-                            let mut s = Stage.lock().unwrap();
-                            s.keyboard.release_key(key)
-                        }},
-                        Event::MouseMotion{{mousestate: m, x, y, ..}} => {{
-                            let mut s = Stage.lock().unwrap();
-                            s.mouse.set_sdl_position([x as f64, y as f64], &window);
+                        // F5 clicks the green flag and F6 the stop sign.
+                        Event::KeyDown {{keycode: Some(Keycode::F5), repeat: false, ..}} => program.click_flag(Stage.clone()),
+                        Event::KeyDown {{keycode: Some(Keycode::F6), repeat: false, ..}} => program.click_stop(Stage.clone()),
+                        // F7 pauses and resumes, F8 runs a single frame while paused.
+                        Event::KeyDown {{keycode: Some(Keycode::F7), repeat: false, ..}} => {{
+                            if program.is_paused(){{program.resume();}} else {{program.pause();}}
                         }},
-                        Event::MouseButtonDown{{mouse_btn: m, ..}} => {{
-                            let mut s = Stage.lock().unwrap();
-                            s.mouse.set_button_down(m);
-                        }}
-                        Event::MouseButtonUp{{mouse_btn: m, ..}} => {{
-                            let mut s = Stage.lock().unwrap();
-                            s.mouse.set_button_up(m);
-                        }}
-                        _ => ()
+                        Event::KeyDown {{keycode: Some(Keycode::F8), ..}} => program.step_frame(),
+                        event => program.handle_event(event, Stage.clone()),
                     }}
                 }}
             }}
@@ -506,6 +595,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         lib = lib,
         targets = targets.join("\n"),
         clone_content = target_clone_fns.join("\n"),
+        show_controls = !cli.no_controls,
         // sprite1 = generate_target(&project["targets"][1], &block_reference)
     );
 
@@ -586,7 +676,7 @@ fn get_block(
             //println!("{}", input.1[1]);
 
             function = match input.1[1][0].as_u32().unwrap() {
-                4|5|6|7|8 => function.replacen(
+                4..=8 => function.replacen(
                     input.0,
                     &format!(
                         "Value::from({})",
@@ -598,7 +688,14 @@ fn get_block(
                 // 6 => function.replacen(input.0, &input.1[1][1].as_str().unwrap().to_string(), 1), // Positive integer
                 // 7 => function.replacen(input.0, &input.1[1][1].as_str().unwrap().to_string(), 1), // Integer
                 // 8 => function.replacen(input.0, &input.1[1][1].as_str().unwrap().to_string(), 1), // Angle
-                9 => function.replacen(input.0, input.1[1][1].as_str().unwrap(), 1), // Color
+                9 => function.replacen(
+                    input.0,
+                    &format!(
+                        "Value::from({}u32)",
+                        target::hex_color_to_number(input.1[1][1].as_str().unwrap()).unwrap_or(0)
+                    ),
+                    1,
+                ), // Color
                 10 => function.replacen(
                     input.0,
                     &format!(
@@ -628,7 +725,6 @@ fn get_block(
                     unreachable!()
                 }
             };
-            function = function.replacen(input.0, input.1[1][1].as_str().unwrap(), 1);
         } else if input.1[1].is_string() {
             // otherwise, it must be a substack.
             // TODO get more than the first block
@@ -821,7 +917,8 @@ fn create_hat(
     let start_type = match block.1["opcode"].as_str().unwrap() {
         "procedures_call" => return Err(String::from("Custom block")),
         "event_whenflagclicked" => StartType::FlagClicked,
-        "control_start_as_clone" => StartType::StartAsClone(format!("{}_clone", sprite_name)),
+        "event_whenthisspriteclicked" | "event_whenstageclicked" => StartType::SpriteClicked,
+        "control_start_as_clone" => StartType::StartAsClone(sprite_name),
         "procedures_define" => StartType::NoStart,
         _ => StartType::NoStart,
     };
@@ -929,7 +1026,10 @@ fn create_all_hats(
                 if !function_name.contains("procedures_definition") {
                     stacks.push(format!(
                         "let {function_name}_uuid = Uuid::new_v4();
-                        v.push(Thread::new(stack_{function_name}(Some(sprite.clone()),stage.clone(), {function_name}_uuid),{start_type},{function_name}_uuid,Some(sprite.lock().unwrap().uuid)));
+                        v.push(Thread::new({{
+                            let (sprite, stage) = (sprite.clone(), stage.clone());
+                            move || stack_{function_name}(Some(sprite.clone()),stage.clone(), {function_name}_uuid)
+                        }},{start_type},{function_name}_uuid,Some(sprite.lock().unwrap().uuid)));
                         "
                 ));
                 }
@@ -940,8 +1040,9 @@ fn create_all_hats(
                     {{
                         let stage = Stage.lock().unwrap();
                         let {function_name}_uuid = Uuid::new_v4();
+                        let (target, target_stage) = ({name_arg}, Stage.clone());
                         program.add_thread(Thread::new(
-                            stack_{function_name}({name_arg},Stage.clone(), {function_name}_uuid),{start_type}, {function_name}_uuid, {uuid}
+                            move || stack_{function_name}(target.clone(),target_stage.clone(), {function_name}_uuid),{start_type}, {function_name}_uuid, {uuid}
                         ));\n
                     }}
 ",
@@ -1043,6 +1144,8 @@ fn get_lists(target: &JsonValue) -> Result<String, &str> {
 fn generate_target(
     target: &JsonValue,
     block_reference: &HashMap<&str, &str>,
+    fencing: bool,
+    monitors: &JsonValue,
 ) -> Result<String, String> {
     // If the target is the stage
     if target["isStage"].as_bool().unwrap() {
@@ -1073,6 +1176,10 @@ fn generate_target(
                     .tempo({tempo})
                     .video_state({videoState})
                     .video_transparency({videoTransparency})
+                    .clock(program.clock())
+                    .fencing({fencing})
+                    .username(configured_username())
+                    {monitors}
                     {costume}
                     {sounds}
                     {variables}
@@ -1094,6 +1201,7 @@ fn generate_target(
             costume = target_costumes(target),
             sounds = target_sounds(target),
             volume = target["volume"],
+            monitors = get_monitors(monitors),
         ))
     } else {
         /* let function = create_hat(
@@ -1208,7 +1316,7 @@ fn fetch_project_token(id: u32) -> Result<String, &'static str> {
 
 /// Formats the given filename with rustfmt.
 fn format_file(filename: &PathBuf) -> io::Result<Output> {
-    return Command::new("rustfmt").arg(filename).output();
+    Command::new("rustfmt").arg(filename).output()
     //.expect("Could not execute rustfmt");
 }
 
@@ -1223,7 +1331,7 @@ fn create_project(path: &PathBuf) -> Result<(), io::Error> {
     
     [dependencies]
     rand=\"0.8.5\"
-    resvg = \"0.25.0\"
+    resvg = \"0.24.0\"
     chrono = \"0.4.23\"
     uuid = {version = \"1.4.1\", features = [\"v4\",\"fast-rng\"]}
 
@@ -1243,6 +1351,66 @@ fn create_project(path: &PathBuf) -> Result<(), io::Error> {
     Ok(())
 }
 
+/// Where the fonts Scratch uses are downloaded from.
+const FONT_URL: &str =
+    "https://raw.githubusercontent.com/scratchfoundation/scratch-render-fonts/master/src";
+
+/// Put the fonts Scratch uses for text in costumes into the project.
+///
+/// Fonts that are already in the project's font directory are kept.  The
+/// others are copied from `font_source` if there is one, and downloaded from
+/// scratch-render-fonts otherwise.  A font that cannot be found, or is not a
+/// font, is an error, as text in it would otherwise just not be drawn.
+fn get_fonts(path: &Path, font_source: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let font_directory = path.join(target::FONT_DIRECTORY);
+    fs::create_dir_all(&font_directory)?;
+
+    for (family, file) in target::SCRATCH_FONTS {
+        let font_path = font_directory.join(file);
+        if fs::read(&font_path).is_ok_and(|font| is_font(&font)) {
+            continue;
+        }
+
+        let font = match font_source {
+            Some(source) => fs::read(source.join(file)).map_err(|error| {
+                format!(
+                    "Could not read font `{family}` from {}: {error}",
+                    source.join(file).display()
+                )
+            })?,
+            None => download_font(file).map_err(|error| {
+                format!(
+                    "Could not download font `{family}` ({error}). \
+                     The fonts can be copied from a directory instead with --fonts."
+                )
+            })?,
+        };
+
+        if !is_font(&font) {
+            return Err(format!("`{file}` for font `{family}` is not a font").into());
+        }
+        fs::write(font_path, font)?;
+    }
+
+    Ok(())
+}
+
+/// Download a font file from scratch-render-fonts.
+fn download_font(file: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let response = ureq::get(&format!("{FONT_URL}/{file}")).call()?;
+
+    let mut font = Vec::new();
+    response.into_reader().read_to_end(&mut font)?;
+    Ok(font)
+}
+
+/// Whether some data is a font that text can be drawn with.
+fn is_font(data: &[u8]) -> bool {
+    let mut database = resvg::usvg::fontdb::Database::new();
+    database.load_font_data(data.to_vec());
+    !database.is_empty()
+}
+
 /// Download the assets for a target.
 fn get_target_assets(target: &JsonValue, path: &Path) -> Result<(), Box<dyn Error>> {
     // create the asset directory
@@ -1305,16 +1473,54 @@ fn target_costumes(target: &JsonValue) -> String {
         let format = &costume["dataFormat"];
         let costume_name = costume["name"].to_string();
 
-        // TODO handle png files properly
-        if format != "svg" {
-            continue;
-        }
-
         // to_return.push_str(&format!("program.add_costume_{stage_or_sprite}(
         //                                 Costume::new(PathBuf::from(\"assets/{name}/{costumename}.{format}\"),1.0).unwrap(),
         //                                 &mut {name}
         //                             );\n"));
-        to_return.push_str(&format!(".add_costume(Costume::new(&window, String::from(\"{costume_name}\"),PathBuf::from(\"assets/{name}/{costumename}.{format}\"),1.0).unwrap())\n"))
+        let rotation_center_x = costume["rotationCenterX"].as_f32().unwrap_or(0.0);
+        let rotation_center_y = costume["rotationCenterY"].as_f32().unwrap_or(0.0);
+        let bitmap_resolution = costume["bitmapResolution"].as_f32().unwrap_or(1.0);
+
+        to_return.push_str(&format!(".add_costume(program.add_costume(Costume::load(&window, String::from(\"{costume_name}\"),PathBuf::from(\"assets/{name}/{costumename}.{format}\"),1.0).rotation_center({rotation_center_x:?},{rotation_center_y:?}).bitmap_resolution({bitmap_resolution:?})))\n"))
+    }
+
+    to_return
+}
+
+/// Generate the variable and list monitors shown over the stage.
+fn get_monitors(monitors: &JsonValue) -> String {
+    let mut to_return = String::new();
+
+    for monitor in monitors.members() {
+        let (mode, name) = match monitor["opcode"].as_str() {
+            Some("data_variable") => (
+                match monitor["mode"].as_str() {
+                    Some("large") => "MonitorMode::Large",
+                    Some("slider") => "MonitorMode::Slider",
+                    _ => "MonitorMode::Default",
+                },
+                &monitor["params"]["VARIABLE"],
+            ),
+            Some("data_listcontents") => ("MonitorMode::List", &monitor["params"]["LIST"]),
+            // monitors of other reporters are not supported yet.
+            _ => continue,
+        };
+
+        let id = &monitor["id"];
+        let sprite = match monitor["spriteName"].as_str() {
+            Some(sprite_name) => format!(".sprite(String::from(r###\"{sprite_name}\"###))"),
+            None => String::new(),
+        };
+        let x = monitor["x"].as_f32().unwrap_or(0.0);
+        let y = monitor["y"].as_f32().unwrap_or(0.0);
+        let width = monitor["width"].as_f32().unwrap_or(0.0);
+        let height = monitor["height"].as_f32().unwrap_or(0.0);
+        let slider_min = monitor["sliderMin"].as_f32().unwrap_or(0.0);
+        let slider_max = monitor["sliderMax"].as_f32().unwrap_or(100.0);
+        let is_discrete = monitor["isDiscrete"].as_bool().unwrap_or(true);
+        let visible = monitor["visible"].as_bool().unwrap_or(false);
+
+        to_return.push_str(&format!(".add_monitor(Monitor::new(String::from(r###\"{id}\"###),String::from(r###\"{name}\"###),{mode}){sprite}.position({x:?},{y:?}).size({width:?},{height:?}).slider({slider_min:?},{slider_max:?},{is_discrete}).visible({visible}))\n"));
     }
 
     to_return
@@ -1344,6 +1550,26 @@ fn target_sounds(target: &JsonValue) -> String {
     to_return
 }
 
+/// Write the settings file the player reads, unless there already is one, so
+/// that settings are kept when a project is compiled again.
+fn create_config(path: &Path) -> Result<(), io::Error> {
+    let config_path = path.join(target::CONFIG_FILE);
+    if config_path.exists() {
+        return Ok(());
+    }
+
+    let config = format!(
+        "# Settings for the player.
+# The username reported by the username block.  This can also be set with
+# `--username NAME` or the {variable} environment variable.
+username =
+",
+        variable = target::USERNAME_VARIABLE
+    );
+
+    fs::write(config_path, config)
+}
+
 /// Create the readme for a given scratch project
 fn create_readme(json: &json::JsonValue) -> Result<String, Box<dyn Error>> {
     let title = &json["title"].as_str().unwrap();
@@ -0,0 +1,7139 @@
+#![allow(
+    unused_imports,
+    unused_mut,
+    unused_variables,
+    dead_code,
+    non_snake_case
+)]
+
+extern crate rand;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use core::task::{RawWaker, RawWakerVTable, Waker};
+use glium::{
+    implement_vertex, uniform,
+    uniforms::{AsUniformValue, DynamicUniforms},
+    Frame, Surface,
+};
+use glium_sdl2::DisplayBuild;
+use image::{GenericImageView, ImageBuffer, Rgba};
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    libc::sleep,
+    mouse::MouseButton,
+    pixels::Color,
+};
+use std::{
+    boxed::Box,
+    cell::RefCell,
+    collections::VecDeque,
+    collections::{HashMap, HashSet},
+    f32::consts::PI,
+    fmt::{Debug, Display},
+    fs,
+    future::{Future, IntoFuture},
+    hash::Hash,
+    io,
+    ops::Index,
+    path::{Path, PathBuf},
+    pin::Pin,
+    rc::Rc,
+    sync::Mutex,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+use blocks::*;
+
+use self::glium_sdl2::SDL2Facade;
+
+// set the width and height of the stage here
+const SCRATCH_WIDTH: Value = Value::Num(480.0);
+const SCRATCH_HALF_WIDTH: Value = Value::Num(240.0);
+const SCRATCH_HEIGHT: Value = Value::Num(360.0);
+const SCRATCH_HALF_HEIGHT: Value = Value::Num(180.0);
+
+const LIST_ITEM_LIMIT: Value = Value::Num(20000.0); // TODO check this
+
+/// The maximum number of clones that can exist at once.
+const CLONE_LIMIT: usize = 300;
+
+mod blocks {
+    use super::glium_sdl2::SDL2Facade;
+    use super::{
+        color_matches, color_to_rgb, mask_matches, sample_color, toNumber, BubbleKind, Costume,
+        Effect, Monitor, MonitorMode, Number, Question, Rectangle, Stamp, StartType, StopType,
+        String, Wait, CLONE_LIMIT, LIST_ITEM_LIMIT, SCRATCH_HALF_HEIGHT, SCRATCH_HALF_WIDTH,
+    };
+    use super::{Keyboard, Sprite, Stage, Value, Yield};
+    use chrono::{Datelike, TimeZone, Timelike, Utc};
+    use uuid::Uuid;
+    use core::f32::consts::PI;
+    use rand::Rng;
+    use std::io;
+    use std::time::Duration;
+    use std::{
+        collections::HashMap,
+        f32::consts::E,
+        rc::Rc,
+        sync::{Mutex, MutexGuard},
+    };
+
+    /// Whether sprites should be kept on the stage when they move.
+    fn fencing(stage: &Rc<Mutex<Stage>>) -> bool {
+        stage.lock().unwrap().fencing
+    }
+
+    pub fn move_steps(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, steps: Value) {
+        let steps = toNumber(&steps) as f32;
+        let fencing = fencing(&stage);
+
+        let mut sprite = sprite.lock().unwrap(); //shadow
+        let radians = (90.0 - sprite.direction) * PI / 180.0;
+        let x = sprite.x + steps * radians.cos();
+        let y = sprite.y + steps * radians.sin();
+        sprite.set_xy(x, y, fencing);
+    }
+
+    pub fn go_to_xy(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, x: Value, y: Value) {
+        let fencing = fencing(&stage);
+        let mut sprite = sprite.lock().unwrap();
+
+        let x = toNumber(&x) as f32;
+        let y = toNumber(&y) as f32;
+
+        sprite.set_xy(x, y, fencing);
+    }
+
+    pub fn get_target_xy(targetName: Value, stage: Rc<Mutex<Stage>>) -> Option<(Value, Value)> {
+        let (targetX, targetY);
+        let stage = stage.lock().unwrap();
+        if targetName == Value::String(String::from("_mouse_")) {
+            targetX = stage.mouse.x();
+            targetY = stage.mouse.y();
+        } else if targetName == Value::String(String::from("_random_")) {
+            targetX = generate_random(-SCRATCH_HALF_WIDTH, SCRATCH_HALF_WIDTH);
+            targetY = generate_random(-SCRATCH_HALF_HEIGHT, SCRATCH_HALF_HEIGHT);
+        } else {
+            let name: String = targetName.into();
+
+            if let Some(sprite) = stage.get_sprite(name) {
+                let sprite = sprite.lock().unwrap();
+                targetX = Value::from(sprite.x);
+                targetY = Value::from(sprite.y);
+            } else {
+                return None;
+            }
+        }
+        Some((targetX, targetY))
+    }
+
+    pub fn go_to(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, to: Value) {
+        // Find the target before locking the sprite, as the target may need to
+        // lock other sprites.
+        if let Some((x, y)) = get_target_xy(to, stage.clone()) {
+            let fencing = fencing(&stage);
+            let mut sprite = sprite.lock().unwrap();
+            sprite.set_xy(x.into(), y.into(), fencing);
+        }
+    }
+
+    /// Glide to a position over a number of seconds.
+    pub async fn glide_secs_to_xy(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        secs: Value,
+        x: Value,
+        y: Value,
+    ) {
+        glide(
+            sprite,
+            stage,
+            toNumber(&secs) as f32,
+            toNumber(&x) as f32,
+            toNumber(&y) as f32,
+        )
+        .await;
+    }
+
+    /// Glide to the mouse pointer, a random position, or a sprite over a
+    /// number of seconds.  Nothing happens if the sprite does not exist.
+    pub async fn glide_to(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        secs: Value,
+        to: Value,
+    ) {
+        if let Some((x, y)) = get_target_xy(to, stage.clone()) {
+            glide(
+                sprite,
+                stage,
+                toNumber(&secs) as f32,
+                toNumber(&x) as f32,
+                toNumber(&y) as f32,
+            )
+            .await;
+        }
+    }
+
+    /// Move the sprite in a straight line to a position, measured against
+    /// the runtime clock and updating once per frame.
+    async fn glide(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        secs: f32,
+        end_x: f32,
+        end_y: f32,
+    ) {
+        // Like in Scratch, a glide with no duration jumps straight there, but
+        // still yields once.
+        let fencing = fencing(&stage);
+        if secs.is_nan() || secs <= 0.0 {
+            sprite.lock().unwrap().set_xy(end_x, end_y, fencing);
+            Yield::Start.await;
+            return;
+        }
+
+        let clock = stage.lock().unwrap().clock.clone();
+        let start_time = clock.lock().unwrap().now();
+        let (start_x, start_y) = {
+            let sprite = sprite.lock().unwrap();
+            (sprite.x, sprite.y)
+        };
+
+        loop {
+            Yield::Start.await;
+
+            let elapsed = clock
+                .lock()
+                .unwrap()
+                .now()
+                .saturating_sub(start_time)
+                .as_secs_f32();
+            let mut sprite = sprite.lock().unwrap();
+            if elapsed < secs {
+                let fraction = elapsed / secs;
+                sprite.set_xy(
+                    start_x + (end_x - start_x) * fraction,
+                    start_y + (end_y - start_y) * fraction,
+                    fencing,
+                );
+            } else {
+                sprite.set_xy(end_x, end_y, fencing);
+                return;
+            }
+        }
+    }
+
+    pub fn turn_right(sprite: Rc<Mutex<Sprite>>, degrees: Value) {
+        let mut sprite = sprite.lock().unwrap();
+        let direction = sprite.direction + toNumber(&degrees) as f32;
+        sprite.set_direction(direction);
+    }
+
+    pub fn turn_left(sprite: Rc<Mutex<Sprite>>, degrees: Value) {
+        let mut sprite = sprite.lock().unwrap();
+        let direction = sprite.direction - toNumber(&degrees) as f32;
+        sprite.set_direction(direction);
+    }
+
+    pub fn point_in_direction(sprite: Rc<Mutex<Sprite>>, degrees: Value) {
+        let mut sprite = sprite.lock().unwrap();
+        sprite.set_direction(toNumber(&degrees) as f32);
+    }
+
+    /// Point towards the mouse pointer or a sprite, or in a random direction.
+    pub fn point_towards(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, towards: Value) {
+        if towards == Value::String(String::from("_random_")) {
+            let direction = rand::thread_rng().gen_range(0..=360) as f32 - 180.0;
+            sprite.lock().unwrap().set_direction(direction);
+            return;
+        }
+
+        // Find the target before locking the sprite, as the target may need to
+        // lock other sprites.
+        let Some((target_x, target_y)) = get_target_xy(towards, stage) else {
+            return;
+        };
+
+        let mut sprite = sprite.lock().unwrap();
+        let dx = toNumber(&target_x) as f32 - sprite.x;
+        let dy = toNumber(&target_y) as f32 - sprite.y;
+        sprite.set_direction(90.0 - dy.atan2(dx).to_degrees());
+    }
+
+    /// Get the distance to the mouse pointer or a sprite.  Like in Scratch,
+    /// the distance from the stage or to a sprite that does not exist is 10000.
+    pub fn distance_to(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        to: Value,
+    ) -> Value {
+        let Some(sprite) = sprite else {
+            return Value::from(10000);
+        };
+        if to == Value::String(String::from("_random_")) {
+            return Value::from(10000);
+        }
+        let Some((target_x, target_y)) = get_target_xy(to, stage) else {
+            return Value::from(10000);
+        };
+
+        let sprite = sprite.lock().unwrap();
+        let dx = sprite.x - toNumber(&target_x) as f32;
+        let dy = sprite.y - toNumber(&target_y) as f32;
+        Value::from((dx * dx + dy * dy).sqrt())
+    }
+
+    pub fn set_x(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, x: Value) {
+        let fencing = fencing(&stage);
+        let mut sprite = sprite.lock().unwrap();
+        let y = sprite.y;
+        sprite.set_xy(toNumber(&x) as f32, y, fencing);
+    }
+    pub fn set_y(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, y: Value) {
+        let fencing = fencing(&stage);
+        let mut sprite = sprite.lock().unwrap();
+        let x = sprite.x;
+        sprite.set_xy(x, toNumber(&y) as f32, fencing);
+    }
+
+    pub fn get_x(sprite: Rc<Mutex<Sprite>>) -> Value {
+        Value::from(sprite.lock().unwrap().x)
+    }
+
+    pub fn get_y(sprite: Rc<Mutex<Sprite>>) -> Value {
+        Value::from(sprite.lock().unwrap().y)
+    }
+
+    pub fn change_x_by(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, x: Value) {
+        let fencing = fencing(&stage);
+        let mut sprite = sprite.lock().unwrap();
+        let (x, y) = (sprite.x + toNumber(&x) as f32, sprite.y);
+        sprite.set_xy(x, y, fencing);
+    }
+    pub fn change_y_by(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, y: Value) {
+        let fencing = fencing(&stage);
+        let mut sprite = sprite.lock().unwrap();
+        let (x, y) = (sprite.x, sprite.y + toNumber(&y) as f32);
+        sprite.set_xy(x, y, fencing);
+    }
+
+    /// If the sprite is touching the edge of the stage, point it away from the
+    /// nearest edge and move it back onto the stage.
+    pub fn if_on_edge_bounce(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>) {
+        let fencing = fencing(&stage);
+        let mut sprite = sprite.lock().unwrap();
+        let Some(bounds) = sprite.bounds() else {
+            return;
+        };
+
+        let half_width: f32 = SCRATCH_HALF_WIDTH.into();
+        let half_height: f32 = SCRATCH_HALF_HEIGHT.into();
+
+        // The distance from each edge; 0 if the sprite is over it.
+        let edges = [
+            (Edge::Left, (half_width + bounds.left).max(0.0)),
+            (Edge::Top, (half_height - bounds.top).max(0.0)),
+            (Edge::Right, (half_width - bounds.right).max(0.0)),
+            (Edge::Bottom, (half_height + bounds.bottom).max(0.0)),
+        ];
+        // Ties go to the first edge, as in Scratch.
+        let (nearest_edge, min_distance) =
+            edges
+                .into_iter()
+                .fold((Edge::Left, f32::INFINITY), |nearest, edge| {
+                    if edge.1 < nearest.1 {
+                        edge
+                    } else {
+                        nearest
+                    }
+                });
+
+        if min_distance > 0.0 {
+            return;
+        }
+
+        let radians = (90.0 - sprite.direction).to_radians();
+        let mut dx = radians.cos();
+        let mut dy = -radians.sin();
+        match nearest_edge {
+            Edge::Left => dx = dx.abs().max(0.2),
+            Edge::Top => dy = dy.abs().max(0.2),
+            Edge::Right => dx = -dx.abs().max(0.2),
+            Edge::Bottom => dy = -dy.abs().max(0.2),
+        }
+        sprite.set_direction(dy.atan2(dx).to_degrees() + 90.0);
+
+        // Move the sprite so it is completely on the stage.
+        let (x, y) = sprite.keep_in_fence(sprite.x, sprite.y);
+        sprite.set_xy(x, y, fencing);
+    }
+
+    /// An edge of the stage.
+    #[derive(Debug, Clone, Copy)]
+    enum Edge {
+        Left,
+        Top,
+        Right,
+        Bottom,
+    }
+
+    /// Set the rotation style.  Unknown styles are ignored.
+    pub fn set_rotation_style(sprite: Rc<Mutex<Sprite>>, style: Value) {
+        use super::RotationStyle;
+        let mut sprite = sprite.lock().unwrap();
+
+        if let Ok(style) = RotationStyle::from_str(&style.to_string()) {
+            sprite.rotation_style = style;
+        }
+    }
+
+    /// Switch the backdrop.
+    ///
+    /// Numbers are treated as indexes, while anything else is first treated
+    /// as a backdrop name, then previous/next/random backdrop, and finally
+    /// cast to a number and tested as an index.
+    pub fn switch_backdrop(stage: Rc<Mutex<Stage>>, backdrop: Value) {
+        let mut stage = stage.lock().unwrap();
+
+        match backdrop {
+            Value::Num(x) => stage.set_costume((x - 1.0) as f32),
+            Value::Null => (),
+            // Like Scratch, anything else is looked up as a backdrop name
+            // first, then used as a number if it is one.  Booleans count as
+            // numbers, so `true` is backdrop 1.
+            backdrop => {
+                let name = String(&backdrop);
+                let number = match backdrop {
+                    Value::Bool(_) => Some(toNumber(&backdrop)),
+                    _ => name.parse::<f64>().ok(),
+                };
+
+                let current_costume = stage.costume;
+
+                let index = stage.costumes.iter().position(|c| c.name == name);
+                if let Some(i) = index {
+                    stage.set_costume(i as f32);
+                } else if name == "next backdrop" {
+                    stage.set_costume(current_costume as f32 + 1.0);
+                } else if name == "previous backdrop" {
+                    stage.set_costume(current_costume as f32 - 1.0);
+                } else if name == "random backdrop" {
+                    if !stage.costumes.len() > 1 {
+                        let mut newIndex: usize = stage.costume;
+                        // generate a random costume, not including the current costume.
+                        while newIndex == stage.costume {
+                            newIndex =
+                                generate_random(0.into(), stage.costumes.len().into()).into();
+                        }
+                        stage.set_costume(newIndex as f32);
+                    }
+                // try to cast the value into a number and use it as an index.
+                } else if let Some(index) = number {
+                    stage.set_costume((index - 1.0) as f32);
+                } else {
+                    // do nothing
+                }
+            }
+        };
+    }
+
+    pub fn next_backdrop(stage: Rc<Mutex<Stage>>) {
+        switch_backdrop(stage, Value::String("next backdrop".to_string()));
+    }
+
+    /// Switch the current costume.
+    ///
+    /// Number values are treated as indexes. String values are first treated as
+    /// costume names and then are attempted to be parsed as indexes.
+    pub fn switch_costume(sprite: Rc<Mutex<Sprite>>, costume: Value) {
+        let mut sprite = sprite.lock().unwrap();
+        match costume {
+            Value::Num(index) => sprite.set_costume((index - 1.0) as f32),
+            Value::Null => (),
+            // Like Scratch, anything else is looked up as a costume name
+            // first, then used as a number if it is one.  Booleans count as
+            // numbers, so `true` is costume 1.
+            costume => {
+                let name = String(&costume);
+                let number = match costume {
+                    Value::Bool(_) => Some(toNumber(&costume)),
+                    _ => name.parse::<f64>().ok(),
+                };
+
+                let current_costume = sprite.costume as f32;
+                if let Some(index) = sprite.costumes.iter().position(|c| c.name == name) {
+                    sprite.set_costume(index as f32);
+                } else if name == "next costume" {
+                    sprite.set_costume(current_costume + 1.0);
+                } else if name == "previous costume" {
+                    sprite.set_costume(current_costume - 1.0);
+                } else if let Some(index) = number {
+                    sprite.set_costume((index - 1.0) as f32);
+                }
+            }
+        }
+    }
+
+    pub fn next_costume(sprite: Rc<Mutex<Sprite>>) {
+        switch_costume(sprite, Value::String("next costume".to_string()));
+    }
+    pub fn previous_costume(sprite: Rc<Mutex<Sprite>>) {
+        switch_costume(sprite, Value::String("previous costume".to_string()));
+    }
+
+    pub fn set_size(sprite: Rc<Mutex<Sprite>>, size: Value) {
+        let mut sprite = sprite.lock().unwrap();
+
+        sprite.size = size.into();
+    }
+
+    pub fn change_size(sprite: Rc<Mutex<Sprite>>, change: Value) {
+        let mut sprite = sprite.lock().unwrap();
+        sprite.size += <Value as Into<f32>>::into(change);
+    }
+
+    pub fn show(sprite: Rc<Mutex<Sprite>>) {
+        let mut sprite = sprite.lock().unwrap();
+        sprite.visible = true;
+    }
+
+    pub fn hide(sprite: Rc<Mutex<Sprite>>) {
+        let mut sprite = sprite.lock().unwrap();
+        sprite.visible = false;
+        sprite.bubble = None;
+    }
+
+    /// Move the current sprite to the front or back layer
+    pub fn go_to_front_or_back(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, arg: Value) {
+        let mut stage = stage.lock().unwrap();
+
+        if String(&arg) == "front" {
+            // go to the front
+
+            // get the current layer
+            let current_layer = { sprite.lock().unwrap().layer };
+
+            // find the largest layer
+            let largest_layer = {
+                stage
+                    .sprites
+                    .iter()
+                    .max_by_key(|sprite| sprite.lock().unwrap().layer)
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .layer
+            };
+
+            for sprite in stage
+                .sprites
+                .iter_mut()
+                .filter(|sprite| sprite.lock().unwrap().layer > current_layer)
+            {
+                sprite.lock().unwrap().layer -= 1;
+            }
+
+            sprite.lock().unwrap().layer = largest_layer;
+        } else {
+            // go to the back (smallest layer #)
+
+            let current_layer = { sprite.lock().unwrap().layer };
+
+            // find the smallest layer
+            let smallest_layer = {
+                stage
+                    .sprites
+                    .iter()
+                    .min_by_key(|sprite| sprite.lock().unwrap().layer)
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .layer
+            };
+
+            // Increment layer numbers for all sprites with layers less than the current layer.
+            for sprite in stage
+                .sprites
+                .iter_mut()
+                .filter(|sprite| sprite.lock().unwrap().layer < current_layer)
+            {
+                sprite.lock().unwrap().layer += 1;
+            }
+
+            // set the current layer to the smallest possible
+            sprite.lock().unwrap().layer = smallest_layer;
+        }
+        stage
+            .sprites
+            .sort_by_key(|sprite| sprite.lock().unwrap().layer);
+    }
+
+    pub fn go_forward_backwards_layers(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        front_back: Value,
+        layers: Value,
+    ) {
+        if String(&front_back) == "forward" {
+            go_forward_layers(sprite, stage, layers);
+        } else {
+            go_backward_layers(sprite, stage, layers);
+        }
+    }
+
+    pub fn go_forward_layers(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, layers: Value) {
+        let mut stage = stage.lock().unwrap();
+        let layers = toNumber(&layers) as u32;
+
+        // retreive the current layer
+        let current_layer = { sprite.lock().unwrap().layer };
+
+        // find the future layer
+        let mut future_layer = current_layer + layers;
+
+        // make sure the future layer is not greater than the current maximum layer
+        let max_layer = {
+            stage
+                .sprites
+                .iter()
+                .max_by_key(|sprite| sprite.lock().unwrap().layer)
+                .unwrap()
+                .lock()
+                .unwrap()
+                .layer
+        };
+        if future_layer > max_layer {
+            future_layer = max_layer
+        }
+
+        // decrease items between the source and destination items
+        for sprite in stage.sprites.iter_mut().filter(|sprite| {
+            let x = sprite.lock().unwrap().layer;
+            x > current_layer && x <= future_layer
+        }) {
+            sprite.lock().unwrap().layer -= 1;
+        }
+
+        // set the current layer to the destination layer
+        {
+            sprite.lock().unwrap().layer = future_layer;
+        }
+
+        // sort the list by layer
+        stage
+            .sprites
+            .sort_by_key(|sprite| sprite.lock().unwrap().layer)
+    }
+
+    pub fn go_backward_layers(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>, layers: Value) {
+        let mut stage = stage.lock().unwrap();
+        let layers = toNumber(&layers) as u32;
+
+        // retreive the current layer
+        let current_layer = { sprite.lock().unwrap().layer };
+
+        // Set the future layer.  The lowest value is 0
+        let mut future_layer = current_layer.checked_sub(layers).unwrap_or(1);
+        if future_layer == 0 {
+            future_layer = 1;
+        }
+
+        for sprite in stage.sprites.iter_mut().filter(|sprite| {
+            let x = sprite.lock().unwrap().layer;
+            x < current_layer && x >= future_layer
+        }) {
+            sprite.lock().unwrap().layer += 1;
+        }
+
+        {
+            sprite.lock().unwrap().layer = future_layer;
+        }
+
+        stage
+            .sprites
+            .sort_by_key(|sprite| sprite.lock().unwrap().layer)
+    }
+
+    pub fn play_sound(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        sound_name: Value,
+    ) -> f32 {
+        if let Some(x) = sprite {
+            let sprite = x.lock().unwrap();
+            if let Some(sound) = sprite
+                .sounds
+                .iter()
+                .find(|x| *x.name == String(&sound_name))
+            {
+                let seconds_duration = sound.sample_count as f32 / sound.rate as f32;
+                // music::play_sound(
+                //     &sound_name,
+                //     music::Repeat::Times(0),
+                //     (sprite.volume as f64) / 100.0,
+                // );
+                return seconds_duration;
+            }
+        } else {
+            let stage = stage.lock().unwrap();
+            if let Some(sound) = stage.sounds.iter().find(|x| *x.name == String(&sound_name)) {
+                let seconds_duration = sound.sample_count as f32 / sound.rate as f32;
+                // music::play_sound(
+                //     &sound_name,
+                //     music::Repeat::Times(0),
+                //     (stage.volume as f64) / 100.0,
+                // );
+                return seconds_duration;
+            }
+        }
+        0.0
+    }
+
+    pub fn set_volume(sprite: Option<Rc<Mutex<Sprite>>>, stage: Rc<Mutex<Stage>>, volume: Value) {
+        if let Some(sprite) = sprite {
+            let mut sprite = sprite.lock().unwrap();
+
+            sprite.volume = Into::<f32>::into(volume).clamp(0.0, 100.0);
+        } else {
+            let mut stage = stage.lock().unwrap();
+
+            stage.volume = Into::<f32>::into(volume).clamp(0.0, 100.0);
+        }
+    }
+
+    pub fn change_volume(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        changeBy: Value,
+    ) {
+        if let Some(sprite) = sprite {
+            let mut sprite = sprite.lock().unwrap();
+
+            sprite.volume += Into::<f32>::into(changeBy);
+            sprite.volume = sprite.volume.clamp(0.0, 100.0);
+        } else {
+            let mut stage = stage.lock().unwrap();
+
+            stage.volume += Into::<f32>::into(changeBy);
+            stage.volume = stage.volume.clamp(0.0, 100.0);
+        }
+    }
+
+    pub fn get_volume(sprite: Option<Rc<Mutex<Sprite>>>, stage: Rc<Mutex<Stage>>) -> Value {
+        if let Some(sprite) = sprite {
+            let sprite = sprite.lock().unwrap();
+            Value::from(sprite.volume)
+        } else {
+            let stage = stage.lock().unwrap();
+            Value::from(stage.volume)
+        }
+    }
+
+    /// Get an attribute of the stage or another sprite, such as its x
+    /// position, backdrop number or one of its variables by name.
+    ///
+    /// Like Scratch, clones are never looked at, sprites only report their
+    /// own variables, and anything that does not exist reports 0.
+    pub fn attribute_of(stage: Rc<Mutex<Stage>>, property: Value, object: Value) -> Value {
+        let stage = stage.lock().unwrap();
+        let property = String(&property);
+
+        let variable = |variables: &HashMap<String, (String, Value)>| {
+            variables
+                .values()
+                .find(|(name, _)| *name == property)
+                .map_or(Value::Num(0.0), |(_, value)| value.clone())
+        };
+        let costume_name = |costumes: &[Rc<Costume>], costume: usize| {
+            costumes.get(costume).map_or(Value::Num(0.0), |costume| {
+                Value::String(costume.name.clone())
+            })
+        };
+
+        if String(&object) == "_stage_" {
+            return match property.as_str() {
+                "background #" | "backdrop #" => Value::from(stage.costume + 1),
+                "backdrop name" => costume_name(&stage.costumes, stage.costume),
+                "volume" => Value::from(stage.volume),
+                _ => variable(&stage.variables),
+            };
+        }
+
+        let Some(sprite) = stage.get_sprite(String(&object)) else {
+            return Value::Num(0.0);
+        };
+        let sprite = sprite.lock().unwrap();
+
+        match property.as_str() {
+            "x position" => Value::from(sprite.x),
+            "y position" => Value::from(sprite.y),
+            "direction" => Value::from(sprite.direction),
+            "costume #" => Value::from(sprite.costume + 1),
+            "costume name" => costume_name(&sprite.costumes, sprite.costume),
+            "size" => Value::from(sprite.size),
+            "volume" => Value::from(sprite.volume),
+            _ => variable(&sprite.variables),
+        }
+    }
+
+    /// Get a variable from an id.
+    pub fn get_variable(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        id: &str,
+    ) -> Value {
+        let stage = stage.lock().unwrap();
+        match sprite {
+            Some(sprite) => {
+                // there is a sprite
+                let sprite = sprite.lock().unwrap();
+
+                // if the variable is on the sprite, get it.
+                let mut var = sprite.variables.get(id);
+
+                // otherwise, check the stage for the variable
+                if var.is_none() {
+                    var = stage.variables.get(id)
+                }
+
+                // return the variable's value
+                var.expect("no such variable found").1.clone()
+            }
+            None => {
+                let var = stage.variables.get(id).unwrap();
+
+                var.1.clone()
+            }
+        }
+    }
+
+    pub fn set_variable(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        (name, id): (String, String),
+        value: Value,
+    ) {
+        let mut stage = stage.lock().unwrap();
+        match sprite {
+            Some(sprite) => {
+                let mut sprite = sprite.lock().unwrap();
+                if let Some(variable) = sprite.variables.get_mut(&id) {
+                    variable.1 = value;
+                } else if let Some(variable) = stage.variables.get_mut(&id) {
+                    variable.1 = value;
+                } else {
+                    sprite.variables.insert(id, (name, value));
+                }
+            }
+            None => {
+                if let Some(variable) = stage.variables.get_mut(&id) {
+                    variable.1 = value;
+                } else {
+                    stage.variables.insert(id, (name, value));
+                }
+            }
+        }
+    }
+
+    pub fn show_variable(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        variable: (String, String),
+    ) {
+        set_monitor_visible(sprite, stage, variable, false, true);
+    }
+
+    pub fn hide_variable(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        variable: (String, String),
+    ) {
+        set_monitor_visible(sprite, stage, variable, false, false);
+    }
+
+    pub fn show_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        list: (String, String),
+    ) {
+        set_monitor_visible(sprite, stage, list, true, true);
+    }
+
+    pub fn hide_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        list: (String, String),
+    ) {
+        set_monitor_visible(sprite, stage, list, true, false);
+    }
+
+    /// Show or hide the monitor of a variable or list.  Like in Scratch,
+    /// showing a variable without a monitor creates one, stacked down the
+    /// left of the stage.
+    fn set_monitor_visible(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        (name, id): (String, String),
+        list: bool,
+        visible: bool,
+    ) {
+        let mut stage = stage.lock().unwrap();
+
+        if let Some(monitor) = stage
+            .monitors
+            .iter_mut()
+            .find(|monitor| monitor.id == id && monitor.is_list() == list)
+        {
+            monitor.visible = visible;
+            return;
+        }
+
+        if !visible {
+            return;
+        }
+
+        // Variables and lists that are not on the sprite are global.
+        let sprite_name = sprite.and_then(|sprite| {
+            let sprite = sprite.lock().unwrap();
+            let is_local = if list {
+                sprite.lists.contains_key(&id)
+            } else {
+                sprite.variables.contains_key(&id)
+            };
+            is_local.then(|| sprite.name.clone())
+        });
+
+        let mode = if list {
+            MonitorMode::List
+        } else {
+            MonitorMode::Default
+        };
+        let mut monitor =
+            Monitor::new(id, name, mode).position(5.0, 5.0 + 32.0 * stage.monitors.len() as f32);
+        monitor.sprite_name = sprite_name;
+        stage.monitors.push(monitor);
+    }
+
+    pub fn change_variable(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        (name, id): (String, String),
+        value: Value,
+    ) {
+        set_variable(
+            sprite.clone(),
+            stage.clone(),
+            (name, id.clone()),
+            get_variable(sprite, stage, &id) + value,
+        );
+    }
+
+    /// Get a list.
+    fn get_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        (name, id): (String, String),
+    ) -> Vec<Value> {
+        let mut stage = stage.lock().unwrap();
+        let list;
+        match sprite {
+            Some(sprite) => {
+                let mut sprite = sprite.lock().unwrap();
+                if let Some(l) = sprite.lists.get(&id) {
+                    list = l.clone();
+                } else if let Some(l) = stage.lists.get(&id) {
+                    list = l.clone();
+                } else {
+                    stage.lists.insert(id.clone(), (name, Vec::new()));
+                    list = stage.lists.get(&id).expect("Just created id").clone();
+                }
+            }
+            None => {
+                if let Some(l) = stage.lists.get(&id) {
+                    list = l.clone();
+                } else {
+                    stage.lists.insert(id.clone(), (name, Vec::new()));
+                    list = stage.lists.get(&id).expect("Just created id").clone();
+                }
+            }
+        }
+
+        list.1
+    }
+
+    fn replace_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        to_replace: Vec<Value>,
+        (name, id): (String, String),
+    ) {
+        let mut stage = stage.lock().unwrap();
+
+        match sprite {
+            Some(sprite) => {
+                let mut sprite = sprite.lock().unwrap();
+                if let Some(l) = sprite.lists.get_mut(&id) {
+                    l.1 = to_replace;
+                } else if let Some(l) = stage.lists.get_mut(&id) {
+                    l.1 = to_replace;
+                } else {
+                    stage.lists.insert(id.clone(), (name, Vec::new()));
+                    stage.lists.get_mut(&id).expect("Just created id").1 = to_replace;
+                }
+            }
+            None => {
+                if let Some(l) = stage.lists.get_mut(&id) {
+                    l.1 = to_replace;
+                } else {
+                    stage.lists.insert(id.clone(), (name, Vec::new()));
+                    stage.lists.get_mut(&id).expect("Just created id").1 = to_replace;
+                }
+            }
+        }
+    }
+
+    /// Get a list in a single value, suitable for printing.
+    pub fn get_list_contents(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        (name, id): (String, String),
+    ) -> Value {
+        let list = get_list(sprite, stage, (name, id));
+
+        // check if the list is all single letters.  If it is, return
+        // the list without separators.  If it is not, join them with a space.
+        let mut all_single_letters = true;
+        for i in &list {
+            if let Value::String(x) = i {
+                if x.len() > 1 {
+                    all_single_letters = false;
+                    break;
+                }
+            }
+        }
+
+        if all_single_letters {
+            // join the list items together with no space
+            Value::String(
+                list.iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join(""),
+            )
+        } else {
+            // join the list items together with a space in between
+            Value::String(
+                list.iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            )
+        }
+    }
+
+    pub fn get_item_of_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        item: Value,
+        (name, id): (String, String),
+    ) -> Value {
+        let list = get_list(sprite, stage, (name, id));
+
+        let index = item.to_list_index(list.len(), false);
+
+        match index {
+            Ok(i) => list[i - 1].clone(),
+            Err(_) => Value::String(String::from("")),
+        }
+    }
+
+    pub fn length_of_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        (name, id): (String, String),
+    ) -> Value {
+        let list = get_list(sprite, stage, (name, id));
+        Value::Num(list.len() as f64)
+    }
+
+    pub fn get_item_num_in_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        item: Value,
+        (name, id): (String, String),
+    ) -> Value {
+        let list = get_list(sprite, stage, (name, id));
+
+        for (i, value) in list.iter().enumerate() {
+            if *value == item {
+                return Value::Num((i - 1) as f64);
+            }
+        }
+        Value::Num(0 as f64)
+    }
+
+    pub fn list_contains_item(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        (name, id): (String, String),
+        search: Value,
+    ) -> Value {
+        let list = get_list(sprite, stage, (name, id));
+
+        for item in list {
+            if item == search {
+                return Value::Bool(true);
+            }
+        }
+        Value::Bool(false)
+    }
+
+    // Get a mutable reference to a list in the sprite or stage.
+    /*fn get_mut_list<'a>(sprite:Option<&'a mut MutexGuard<'a, Sprite>>,stage:&'a mut MutexGuard<'a, Stage>,(name,id):(String,String))-> &'a mut (String,Vec<Value>){
+        match sprite{
+            // if a sprite is specified:
+            Some(mut sprite) => {
+                if let Some(l) = sprite.lists.get_mut(&id){
+                    // if the list exists in the sprite, return it.
+                    return l;
+                }else if let Some(l) =stage.lists.get_mut(&id){
+                    // otherwise, look in the stage, and if the stage has it, return it.
+                    return l;
+                }else{
+                    // else create a new list in the sprite, and return it.
+                    stage.lists.insert(id.clone(),(name,Vec::new()));
+                    return stage.lists.get_mut(&id).expect("Just created id");
+                }
+            },
+            None => {
+                // if a sprite is not specified:
+                if let Some(l) = stage.lists.get_mut(&id){
+                    // check if the list exists in the stage, and if so, return it.
+                    return l;
+                }else{
+                    // otherwise create a new list in the stage and return it.
+                    stage.lists.insert(id.clone(),(name,Vec::new()));
+                    return stage.lists.get_mut(&id).expect("Just created id");
+                }
+            },
+        }
+
+    }*/
+
+    pub fn add_to_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        to_add: Value,
+        (name, id): (String, String),
+    ) {
+        let mut stage = stage.lock().unwrap();
+        match sprite {
+            Some(sprite) => {
+                let mut sprite = sprite.lock().unwrap();
+                if let Some(l) = sprite.lists.get_mut(&id) {
+                    l.1.push(to_add);
+                } else if let Some(l) = stage.lists.get_mut(&id) {
+                    l.1.push(to_add);
+                } else {
+                    stage.lists.insert(id.clone(), (name, Vec::new()));
+                    let l = stage.lists.get_mut(&id).expect("Just created id");
+                    l.1.push(to_add);
+                }
+            }
+            None => {
+                if let Some(l) = stage.lists.get_mut(&id) {
+                    l.1.push(to_add);
+                } else {
+                    stage.lists.insert(id.clone(), (name, Vec::new()));
+                    let l = stage.lists.get_mut(&id).expect("Just created id");
+                    l.1.push(to_add);
+                }
+            }
+        }
+    }
+
+    /// Delete an item from a list
+    pub fn delete_from_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        item: Value,
+        (name, id): (String, String),
+    ) {
+        let mut list = get_list(sprite.clone(), stage.clone(), (name.clone(), id.clone()));
+        let index = item.to_list_index(list.len(), true);
+
+        // TODO handle deleting all of the list. (This is not supported yet in the .to_list_index() function).
+
+        if let Ok(x) = index {
+            list.remove(x - 1);
+            replace_list(sprite, stage, list, (name, id));
+        }
+    }
+
+    pub fn delete_all_of_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        (name, id): (String, String),
+    ) {
+        replace_list(sprite, stage, Vec::new(), (name, id));
+    }
+
+    pub fn insert_item_in_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        item: Value,
+        position: Value,
+        (name, id): (String, String),
+    ) {
+        let mut list = get_list(sprite.clone(), stage.clone(), (name.clone(), id.clone()));
+        let index = position.to_list_index(list.len() + 1, false);
+
+        if let Ok(x) = index {
+            if x > LIST_ITEM_LIMIT.into() {
+                return;
+            }
+
+            list.insert(x - 1, item);
+
+            if list.len() > LIST_ITEM_LIMIT.into() {
+                list.pop();
+            }
+
+            replace_list(sprite, stage, list, (name, id));
+        }
+    }
+
+    pub fn replace_item_in_list(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        item: Value,
+        position: Value,
+        (name, id): (String, String),
+    ) {
+        let mut list = get_list(sprite.clone(), stage.clone(), (name.clone(), id.clone()));
+        let index = position.to_list_index(list.len(), false);
+
+        if let Ok(x) = index {
+            list[x - 1] = item;
+            replace_list(sprite, stage, list, (name, id));
+        }
+    }
+
+    /// Set the costume.
+    ///
+    /// DEPRECATED
+    fn set_costume(object: Option<&mut Sprite>, globalCostume: &mut usize, costume: usize) {
+        match object {
+            Some(sprite) => sprite.costume = costume,
+            None => *globalCostume = costume,
+        }
+    }
+
+    /// Show a speech bubble, or clear the bubble if the message is empty.
+    pub fn say(sprite: Rc<Mutex<Sprite>>, message: Value) {
+        sprite.lock().unwrap().show_bubble(BubbleKind::Say, message);
+    }
+
+    /// Show a thought bubble, or clear the bubble if the message is empty.
+    pub fn think(sprite: Rc<Mutex<Sprite>>, message: Value) {
+        sprite
+            .lock()
+            .unwrap()
+            .show_bubble(BubbleKind::Think, message);
+    }
+
+    pub async fn say_for_secs(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        message: Value,
+        secs: Value,
+    ) {
+        bubble_for_secs(sprite, stage, BubbleKind::Say, message, secs).await;
+    }
+
+    pub async fn think_for_secs(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        message: Value,
+        secs: Value,
+    ) {
+        bubble_for_secs(sprite, stage, BubbleKind::Think, message, secs).await;
+    }
+
+    /// Show a bubble for a number of seconds.  The bubble is only cleared
+    /// afterwards if no other say or think block has replaced it meanwhile.
+    async fn bubble_for_secs(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        kind: BubbleKind,
+        message: Value,
+        secs: Value,
+    ) {
+        let usage = sprite.lock().unwrap().show_bubble(kind, message);
+
+        wait(stage, secs).await;
+
+        let mut sprite = sprite.lock().unwrap();
+        if sprite
+            .bubble
+            .as_ref()
+            .is_some_and(|bubble| bubble.usage == usage)
+        {
+            sprite.bubble = None;
+        }
+    }
+
+    pub fn set_effect(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        effect: Value,
+        amount: Value,
+    ) {
+        let amount = Number(&amount) as f32;
+        let e = match &*String(&effect).to_lowercase() {
+            "color" => (Effect::Color, amount),
+            "fisheye" => (Effect::Fisheye, amount),
+            "whirl" => (Effect::Whirl, amount),
+            "pixelate" => (Effect::Pixelate, amount),
+            "mosaic" => (Effect::Mosaic, amount),
+            "brightness" => (Effect::Brightness, amount),
+            "ghost" => (Effect::Ghost, amount),
+            _ => return,
+        };
+
+        if let Some(sprite) = sprite {
+            let mut sprite = sprite.lock().unwrap();
+
+            sprite.effects.insert(e.0, e.1);
+        } else {
+            let mut stage = stage.lock().unwrap();
+
+            stage.effects.insert(e.0, e.1);
+        }
+    }
+
+    pub fn change_effect(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        effect: Value,
+        amount: Value,
+    ) {
+        let amount = Number(&amount) as f32;
+        let e = match &*String(&effect).to_lowercase() {
+            "color" => (Effect::Color, amount),
+            "fisheye" => (Effect::Fisheye, amount),
+            "whirl" => (Effect::Whirl, amount),
+            "pixelate" => (Effect::Pixelate, amount),
+            "mosaic" => (Effect::Mosaic, amount),
+            "brightness" => (Effect::Brightness, amount),
+            "ghost" => (Effect::Ghost, amount),
+            _ => return,
+        };
+
+        if let Some(sprite) = sprite {
+            let mut sprite = sprite.lock().unwrap();
+
+            *sprite.effects.entry(e.0).or_insert(0.0) += e.1;
+        } else {
+            let mut stage = stage.lock().unwrap();
+
+            *stage.effects.entry(e.0).or_insert(0.0) += e.1;
+        }
+    }
+
+    pub fn clear_effects(sprite: Option<Rc<Mutex<Sprite>>>, stage: Rc<Mutex<Stage>>) {
+        if let Some(sprite) = sprite {
+            let mut sprite = sprite.lock().unwrap();
+            sprite.effects.clear();
+        } else {
+            let mut stage = stage.lock().unwrap();
+            stage.effects.clear();
+        }
+    }
+
+
+    /// Perform the stop operation: everything, this script, or the other
+    /// scripts in the sprite (or stage) running the block.
+    ///
+    /// Unknown options are ignored and return `StopType::None`.
+    pub fn stop(
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        stage: Rc<Mutex<Stage>>,
+        thread_uuid: Uuid,
+        option: Value,
+    ) -> StopType {
+        match &*option.to_string() {
+            "all" => {
+                stage.lock().unwrap().stop_all = StopType::All;
+                StopType::All
+            }
+            // Returning is enough to end the current script; no other
+            // thread needs to be touched.
+            "this script" => StopType::ThisScript,
+            "other scripts in sprite" | "other scripts in stage" => {
+                let stop_type = StopType::OtherScriptsInSprite { thread_uuid };
+                if let Some(sprite) = sprite {
+                    sprite.lock().unwrap().possible_stop = stop_type;
+                } else {
+                    stage.lock().unwrap().stop_all = stop_type;
+                }
+                stop_type
+            }
+            _ => StopType::None,
+        }
+    }
+
+    /// Join two strings.
+    pub fn join(a: Value, b: Value) -> Value {
+        Value::String(format!("{}{}", a, b))
+    }
+
+    pub fn letter_of(letter: Value, string: Value) -> Value {
+        let mut index: usize = letter.into();
+
+        if let Some(x)=index.checked_sub(1){
+            index = x;
+        }
+        let s: String = string.into();
+
+        Value::from(match s.chars().nth(index) {
+            Some(x) => x.to_string(),
+            None => String::new(),
+        })
+    }
+
+    pub fn length(string: Value) -> Value {
+        Value::Num(string.to_string().len() as f64)
+    }
+
+    pub fn contains(string1: Value, string2: Value) -> Value {
+        Value::Bool(
+            string1
+                .to_string()
+                .to_lowercase()
+                .contains(string2.to_string().to_lowercase().as_str()),
+        )
+    }
+
+    pub fn round(num: Value) -> Value {
+        let n: f64 = num.into();
+        Value::Num(n.round())
+    }
+
+    pub fn modulus(num1: Value, num2: Value) -> Value {
+        let n: f64 = num1.into();
+        let modulus: f64 = num2.into();
+        Value::Num(n % modulus)
+    }
+
+    pub fn mathop(operator: Value, num: Value) -> Value {
+        let n: f64 = num.into();
+        let op: String = operator.into();
+
+        let output = match &*op {
+            "abs" => n.abs(),
+            "floor" => n.floor(),
+            "ceiling" => n.ceil(),
+            "sqrt" => n.sqrt(),
+            "sin" => n.to_radians().sin(),
+            "cos" => n.to_radians().cos(),
+            "tan" => n.to_radians().tan(),
+            "asin" => n.asin().to_degrees(),
+            "acos" => n.acos().to_degrees(),
+            "atan" => n.atan().to_degrees(),
+            "ln" => n.ln(),
+            "log" => n.log(10.0),
+            "e ^" => n.exp(),
+            "10 ^" => 10f64.powf(n),
+            _ => 0.0,
+        };
+
+        Value::Num(output)
+    }
+
+    /// Wait until condition is true.  The condition is checked again every
+    /// frame.
+    pub async fn wait_until<F>(condition: F)
+    where
+        F: Fn() -> Value,
+    {
+        while !Into::<bool>::into(condition()) {
+            Yield::Start.await;
+        }
+    }
+
+    /// Create a clone of a sprite
+    ///
+    /// Procedure:
+    /// - First, clone the sprite and add it to the list of sprites.
+    /// - Then, create new instances of all scripts, attached to the new sprite.
+    ///   This has to be done outside of this function
+    ///
+    /// Returns the name of the original sprite (which is used to find the
+    /// scripts to create) and the new clone, or `None` if no clone was
+    /// created because the target does not exist or the clone limit was hit.
+    pub fn create_clone(
+        stage: Rc<Mutex<Stage>>,
+        sprite: Option<Rc<Mutex<Sprite>>>,
+        to_clone: Value,
+    ) -> Option<(String, Rc<Mutex<Sprite>>)> {
+        let mut stage = stage.lock().unwrap();
+
+        if stage.clone_count() >= CLONE_LIMIT {
+            return None;
+        }
+
+        let to_clone = to_clone.to_string();
+
+        // define the reference to the sprite to be cloned
+        let clone_target = match &*to_clone {
+            "_myself_" => sprite?,
+            x => stage.get_sprite(x.to_string())?,
+        };
+
+        let mut clone = {
+            let clone_target_lock = clone_target.lock().unwrap();
+            let mut clone = clone_target_lock.clone();
+
+            // Clones of clones still refer back to the original sprite.
+            if clone.original.is_none() {
+                clone.original = Some(clone_target.clone());
+            }
+            clone
+        };
+
+        for s in stage
+            .sprites
+            .iter_mut()
+            .filter(|sprite| sprite.lock().unwrap().layer >= clone.layer)
+        {
+            s.lock().unwrap().layer += 1;
+        }
+
+        let name = clone.name.clone();
+        let clone = Rc::new(Mutex::new(clone));
+        stage.add_sprite(clone.clone());
+
+        stage
+            .sprites
+            .sort_by_key(|sprite| sprite.lock().unwrap().layer);
+
+        Some((name, clone))
+    }
+
+    /// Mark the sprite for deletion if it is a clone.
+    ///
+    /// Returns whether the sprite was a clone, in which case the calling
+    /// script should stop.
+    pub fn delete_this_clone(stage: Rc<Mutex<Stage>>, sprite: Rc<Mutex<Sprite>>) -> bool {
+        let mut sprite = sprite.lock().unwrap();
+
+        if !sprite.is_clone() {
+            return false;
+        }
+
+        sprite.to_be_deleted = true;
+        true
+    }
+
+    pub fn generate_random(from: Value, to: Value) -> Value {
+        let from: u32 = from.into();
+        let to: u32 = to.into();
+        let r = rand::thread_rng().gen_range(from..=to);
+        Value::Num(r as f64)
+    }
+
+    pub fn key_pressed(stage: Rc<Mutex<Stage>>, key: Value) -> Value {
+        let mut stage = stage.lock().unwrap();
+        let to_return = stage.keyboard.get_key_down(key);
+        Value::Bool(to_return)
+    }
+
+    pub fn mousex(stage: Rc<Mutex<Stage>>) -> Value {
+        let stage = stage.lock().unwrap();
+
+        stage.mouse.x()
+    }
+
+    pub fn mousey(stage: Rc<Mutex<Stage>>) -> Value {
+        let stage = stage.lock().unwrap();
+
+        stage.mouse.y()
+    }
+
+    /// Set whether the sprite can be dragged with the mouse.
+    pub fn set_drag_mode(sprite: Rc<Mutex<Sprite>>, mode: Value) {
+        let mut sprite = sprite.lock().unwrap();
+
+        match String(&mode).as_str() {
+            "draggable" => sprite.draggable = true,
+            "not draggable" => sprite.draggable = false,
+            _ => (),
+        }
+    }
+
+    pub fn mouse_down(stage: Rc<Mutex<Stage>>) -> Value {
+        let stage = stage.lock().unwrap();
+
+        Value::Bool(stage.mouse.mouse_down())
+    }
+
+    /// Whether the sprite is touching the mouse pointer, the edge of the stage,
+    /// or any copy of another sprite.  Hidden sprites touch nothing.
+    pub fn touching_object(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        object: Value,
+    ) -> Value {
+        let stage = stage.lock().unwrap();
+        let Some(shape) = sprite.lock().unwrap().collision_shape() else {
+            return Value::Bool(false);
+        };
+
+        let touching = match &*String(&object) {
+            "_mouse_" => shape.contains(stage.mouse.scratch_position),
+            "_edge_" => shape.tight_bounds().is_some_and(|bounds| {
+                let (half_width, half_height) = (
+                    f32::from(SCRATCH_HALF_WIDTH),
+                    f32::from(SCRATCH_HALF_HEIGHT),
+                );
+                bounds.left < -half_width
+                    || bounds.right > half_width
+                    || bounds.top > half_height
+                    || bounds.bottom < -half_height
+            }),
+            name => stage
+                .sprites
+                .iter()
+                .filter(|other| !Rc::ptr_eq(other, &sprite))
+                .filter_map(|other| {
+                    let other = other.lock().unwrap();
+                    if other.name == name && !other.to_be_deleted {
+                        other.collision_shape()
+                    } else {
+                        None
+                    }
+                })
+                .any(|other| shape.touches(&other)),
+        };
+
+        Value::Bool(touching)
+    }
+
+    /// Whether the sprite is touching a color anywhere on the stage.
+    pub fn touching_color(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        color: Value,
+    ) -> Value {
+        is_touching_color(sprite, stage, color_to_rgb(&color), None)
+    }
+
+    /// Whether the parts of the sprite that are one color are touching
+    /// another color.
+    pub fn color_is_touching_color(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        color: Value,
+        color2: Value,
+    ) -> Value {
+        is_touching_color(
+            sprite,
+            stage,
+            color_to_rgb(&color2),
+            Some(color_to_rgb(&color)),
+        )
+    }
+
+    /// Check every point of the sprite, or only the points that match `mask`,
+    /// for whether the stage beneath it is `color`.  Hidden sprites touch
+    /// nothing.
+    fn is_touching_color(
+        sprite: Rc<Mutex<Sprite>>,
+        stage: Rc<Mutex<Stage>>,
+        color: [u8; 3],
+        mask: Option<[u8; 3]>,
+    ) -> Value {
+        let stage = stage.lock().unwrap();
+        let Some(shape) = sprite.lock().unwrap().collision_shape() else {
+            return Value::Bool(false);
+        };
+        let Some(bounds) = shape.bounds.intersection(&Rectangle::stage()) else {
+            return Value::Bool(false);
+        };
+
+        let layers = stage.layers_below(&sprite, &bounds);
+
+        let touching = bounds.points().any(|point| {
+            let is_sprite = match (shape.color_at(point), mask) {
+                (Some(pixel), Some(mask)) => mask_matches(pixel, mask),
+                (Some([_, _, _, alpha]), None) => alpha > 0,
+                (None, _) => false,
+            };
+
+            is_sprite && color_matches(sample_color(&layers, point), color)
+        });
+
+        Value::Bool(touching)
+    }
+
+    /// Ask a question and wait until it is answered in the ask box.
+    ///
+    /// Questions asked at the same time are shown one after another, in the
+    /// order they were asked.  Like in Scratch, a sprite that is visible when
+    /// its question comes up asks in a speech bubble, and the ask box is shown
+    /// without the question.
+    pub async fn ask(sprite: Option<Rc<Mutex<Sprite>>>, stage: Rc<Mutex<Stage>>, question: Value) {
+        let id = Uuid::new_v4();
+
+        {
+            let mut stage = stage.lock().unwrap();
+            stage.prompt.questions.push_back(Question {
+                id,
+                text: String(&question),
+                sprite,
+                in_bubble: false,
+            });
+
+            if stage.prompt.questions.len() == 1 {
+                stage.prompt.ask_next_question();
+            }
+        }
+
+        while stage.lock().unwrap().prompt.is_waiting(id) {
+            Yield::Start.await;
+        }
+    }
+
+    /// Wait for a number of seconds.
+    ///
+    /// Like Scratch, negative and NaN durations wait for no time at all, but
+    /// still yield once.
+    pub async fn wait(stage: Rc<Mutex<Stage>>, duration: Value) {
+        let clock = stage.lock().unwrap().clock.clone();
+
+        // `max` also turns NaN into 0.  Durations too large to represent
+        // wait forever.
+        let seconds = toNumber(&duration).max(0.0);
+        let duration = Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX);
+
+        Wait::new(clock, duration).await;
+    }
+
+    /// Get the number of seconds since the timer was last reset.  The timer
+    /// is measured against the runtime clock, so it freezes while paused.
+    pub fn timer(stage: Rc<Mutex<Stage>>) -> Value {
+        let stage = stage.lock().unwrap();
+        let now = stage.clock.lock().unwrap().now();
+
+        Value::from(now.saturating_sub(stage.timer_start).as_secs_f64())
+    }
+
+    pub fn reset_timer(stage: Rc<Mutex<Stage>>) {
+        let mut stage = stage.lock().unwrap();
+        let now = stage.clock.lock().unwrap().now();
+        stage.timer_start = now;
+    }
+
+    pub fn answer(stage: Rc<Mutex<Stage>>) -> Value {
+        let stage = stage.lock().unwrap();
+        stage.get_answer()
+    }
+
+    /// Calculate the days since 2000, to the millisecond.
+    ///
+    /// Scratch counts from local midnight on January 1st 2000, then adds back
+    /// the time zone offset of that day, so the count ends up starting from
+    /// midnight UTC no matter where the project runs.
+    pub fn days_since_2000(stage: Rc<Mutex<Stage>>) -> Value {
+        const MILLISECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+        let now = stage.lock().unwrap().clock.lock().unwrap().date();
+        let start = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let milliseconds = now.timestamp_millis() - start.timestamp_millis();
+
+        Value::from(milliseconds as f64 / MILLISECONDS_PER_DAY)
+    }
+
+    /// Get part of the current local date or time, such as the year or the
+    /// minute.  Months start at 1 for January, and days of the week start at
+    /// 1 for Sunday.  Anything else is 0.
+    pub fn current_date(stage: Rc<Mutex<Stage>>, menu: Value) -> Value {
+        let now = stage.lock().unwrap().clock.lock().unwrap().date();
+
+        match String(&menu).to_lowercase().as_str() {
+            "year" => Value::from(now.year()),
+            "month" => Value::from(now.month()),
+            "date" => Value::from(now.day()),
+            "dayofweek" => Value::from(now.weekday().number_from_sunday()),
+            "hour" => Value::from(now.hour()),
+            "minute" => Value::from(now.minute()),
+            "second" => Value::from(now.second()),
+            _ => Value::Num(0.0),
+        }
+    }
+
+    pub fn username(stage: Rc<Mutex<Stage>>) -> Value {
+        Value::String(stage.lock().unwrap().username.clone())
+    }
+
+    pub fn clear_pen(stage: Rc<Mutex<Stage>>) {
+        let mut stage = stage.lock().unwrap();
+
+        stage.stamps.clear();
+    }
+
+    pub fn stamp(sprite: Rc<Mutex<Sprite>>, stage: Rc<Mutex<Stage>>) {
+        let mut stage = stage.lock().unwrap();
+
+        let sprite = sprite.lock().unwrap();
+
+        if let Some(transform) = sprite.model_matrix() {
+            stage.stamps.push(Stamp {
+                transform,
+                costume: sprite.costumes[sprite.costume].clone(),
+            });
+        }
+    }
+}
+
+mod glium_sdl2 {
+    // Copyright (c) 2016 glium_sdl2 developers
+    // Licensed under the Apache License, Version 2.0
+    // <LICENSE-APACHE or
+    // http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+    // license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+    // at your option. All files in the project carrying such
+    // notice may not be copied, modified, or distributed except
+    // according to those terms.
+
+    //! An SDL2 backend for [Glium](https://github.com/tomaka/glium) - a high-level
+    //! OpenGL wrapper for the Rust language.
+    //!
+    //! # Example
+    //! ```no_run
+    //! # #[macro_use] extern crate glium;
+    //! # extern crate glium_sdl2;
+    //! # extern crate sdl2;
+    //! # fn main() {
+    //! use glium_sdl2::DisplayBuild;
+    //!
+    //! let sdl_context = sdl2::init().unwrap();
+    //! let video_subsystem = sdl_context.video().unwrap();
+    //!
+    //! let display = video_subsystem.window("My window", 800, 600)
+    //!     .resizable()
+    //!     .build_glium()
+    //!     .unwrap();
+    //!
+    //! let mut running = true;
+    //! let mut event_pump = sdl_context.event_pump().unwrap();
+    //!
+    //! while running {
+    //!     let mut target = display.draw();
+    //!     // do drawing here...
+    //!     target.finish().unwrap();
+    //!
+    //!     // Event loop: includes all windows
+    //!
+    //!     for event in event_pump.poll_iter() {
+    //!         use sdl2::event::Event;
+    //!
+    //!         match event {
+    //!             Event::Quit { .. } => {
+    //!                 running = false;
+    //!             },
+    //!             _ => ()
+    //!         }
+    //!     }
+    //! }
+    //! # }
+    //! ```
+
+    extern crate glium;
+    extern crate sdl2;
+
+    use std::cell::UnsafeCell;
+    use std::mem;
+    use std::ops::Deref;
+    use std::os::raw::c_void;
+    use std::rc::Rc;
+
+    use glium::backend::{Backend, Context, Facade};
+    use glium::debug;
+    use glium::IncompatibleOpenGl;
+    use glium::SwapBuffersError;
+    use sdl2::video::{Window, WindowBuildError};
+    use sdl2::VideoSubsystem;
+
+    pub type Display = SDL2Facade;
+
+    #[derive(Debug)]
+    pub enum GliumSdl2Error {
+        WindowBuildError(WindowBuildError),
+        ContextCreationError(String),
+    }
+
+    impl From<String> for GliumSdl2Error {
+        fn from(s: String) -> GliumSdl2Error {
+            GliumSdl2Error::ContextCreationError(s)
+        }
+    }
+
+    impl From<WindowBuildError> for GliumSdl2Error {
+        fn from(err: WindowBuildError) -> GliumSdl2Error {
+            GliumSdl2Error::WindowBuildError(err)
+        }
+    }
+
+    impl From<IncompatibleOpenGl> for GliumSdl2Error {
+        fn from(err: IncompatibleOpenGl) -> GliumSdl2Error {
+            GliumSdl2Error::ContextCreationError(err.to_string())
+        }
+    }
+
+    impl std::error::Error for GliumSdl2Error {
+        fn cause(&self) -> Option<&dyn std::error::Error> {
+            match *self {
+                GliumSdl2Error::WindowBuildError(ref err) => err.source(),
+                GliumSdl2Error::ContextCreationError(_) => None,
+            }
+        }
+    }
+
+    impl std::fmt::Display for GliumSdl2Error {
+        fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+            match *self {
+                GliumSdl2Error::WindowBuildError(ref err) => err.fmt(formatter),
+                GliumSdl2Error::ContextCreationError(ref err) => err.fmt(formatter),
+            }
+        }
+    }
+
+    /// Facade implementation for an SDL2 window.
+    #[derive(Clone)]
+    pub struct SDL2Facade {
+        // contains everything related to the current context and its state
+        context: Rc<Context>,
+
+        backend: Rc<SDL2WindowBackend>,
+    }
+
+    impl Facade for SDL2Facade {
+        fn get_context(&self) -> &Rc<Context> {
+            &self.context
+        }
+    }
+
+    impl Deref for SDL2Facade {
+        type Target = Context;
+
+        fn deref(&self) -> &Context {
+            &self.context
+        }
+    }
+
+    impl SDL2Facade {
+        pub fn window(&self) -> &Window {
+            self.backend.window()
+        }
+
+        /// Start drawing on the backbuffer.
+        ///
+        /// This function returns a `Frame`, which can be used to draw on it.
+        /// When the `Frame` is destroyed, the buffers are swapped.
+        ///
+        /// Note that destroying a `Frame` is immediate, even if vsync is enabled.
+        pub fn draw(&self) -> glium::Frame {
+            glium::Frame::new(
+                self.context.clone(),
+                self.backend.get_framebuffer_dimensions(),
+            )
+        }
+    }
+
+    /// An object that can build a facade object.
+    ///
+    /// This trait is different from `glium::DisplayBuild` because Rust doesn't allow trait
+    /// implementations on types from external crates, unless the trait is in the same crate as the impl.
+    /// To clarify, both `glium::DisplayBuild` and `sdl2::video::WindowBuilder` are in different crates
+    /// than `glium_sdl2`.
+    pub trait DisplayBuild {
+        /// The object that this `DisplayBuild` builds.
+        type Facade: glium::backend::Facade;
+
+        /// The type of error that initialization can return.
+        type Err;
+
+        /// Build a context and a facade to draw on it.
+        ///
+        /// Performs a compatibility check to make sure that all core elements of glium
+        /// are supported by the implementation.
+        fn build_glium(self) -> Result<Self::Facade, Self::Err>
+        where
+            Self: Sized,
+        {
+            self.build_glium_debug(Default::default())
+        }
+
+        /// Build a context and a facade to draw on it.
+        ///
+        /// Performs a compatibility check to make sure that all core elements of glium
+        /// are supported by the implementation.
+        fn build_glium_debug(
+            self,
+            _: debug::DebugCallbackBehavior,
+        ) -> Result<Self::Facade, Self::Err>;
+
+        /// Build a context and a facade to draw on it
+        ///
+        /// This function does the same as `build_glium`, except that the resulting context
+        /// will assume that the current OpenGL context will never change.
+        unsafe fn build_glium_unchecked(self) -> Result<Self::Facade, Self::Err>
+        where
+            Self: Sized,
+        {
+            self.build_glium_unchecked_debug(Default::default())
+        }
+
+        /// Build a context and a facade to draw on it
+        ///
+        /// This function does the same as `build_glium`, except that the resulting context
+        /// will assume that the current OpenGL context will never change.
+        unsafe fn build_glium_unchecked_debug(
+            self,
+            _: debug::DebugCallbackBehavior,
+        ) -> Result<Self::Facade, Self::Err>;
+
+        // TODO
+        // Changes the settings of an existing facade.
+        // fn rebuild_glium(self, &Self::Facade) -> Result<(), Self::Err>;
+    }
+
+    impl DisplayBuild for &mut sdl2::video::WindowBuilder {
+        type Facade = SDL2Facade;
+        type Err = GliumSdl2Error;
+
+        fn build_glium_debug(
+            self,
+            debug: debug::DebugCallbackBehavior,
+        ) -> Result<SDL2Facade, GliumSdl2Error> {
+            let backend = Rc::new(SDL2WindowBackend::new(self)?);
+            let context = unsafe { Context::new(backend.clone(), true, debug) }?;
+
+            let display = SDL2Facade { context, backend };
+
+            Ok(display)
+        }
+
+        unsafe fn build_glium_unchecked_debug(
+            self,
+            debug: debug::DebugCallbackBehavior,
+        ) -> Result<SDL2Facade, GliumSdl2Error> {
+            let backend = Rc::new(SDL2WindowBackend::new(self)?);
+            let context = Context::new(backend.clone(), false, debug)?;
+
+            let display = SDL2Facade { context, backend };
+
+            Ok(display)
+        }
+    }
+
+    pub struct SDL2WindowBackend {
+        window: UnsafeCell<sdl2::video::Window>,
+        context: sdl2::video::GLContext,
+    }
+
+    impl SDL2WindowBackend {
+        fn subsystem(&self) -> &VideoSubsystem {
+            self.window().subsystem()
+        }
+
+        fn window(&self) -> &Window {
+            // The window is only ever borrowed immutably.
+            unsafe { &*self.window.get() }
+        }
+
+        pub fn new(
+            window_builder: &mut sdl2::video::WindowBuilder,
+        ) -> Result<SDL2WindowBackend, GliumSdl2Error> {
+            let window = window_builder.opengl().build()?;
+            let context = window.gl_create_context()?;
+
+            Ok(SDL2WindowBackend {
+                window: UnsafeCell::new(window),
+                context,
+            })
+        }
+    }
+
+    unsafe impl Backend for SDL2WindowBackend {
+        fn swap_buffers(&self) -> Result<(), SwapBuffersError> {
+            self.window().gl_swap_window();
+
+            // AFAIK, SDL or `SDL_GL_SwapWindow` doesn't have any way to detect context loss.
+            // TODO: Find out if context loss is an issue in SDL2 (especially for the Android port).
+
+            Ok(())
+        }
+
+        unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+            // Assumes the appropriate context for the window has been set before this call.
+
+            self.subsystem().gl_get_proc_address(symbol) as *const c_void
+        }
+
+        fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+            self.window().drawable_size()
+        }
+
+        fn is_current(&self) -> bool {
+            self.context.is_current()
+        }
+
+        unsafe fn make_current(&self) {
+            self.window().gl_make_current(&self.context).unwrap()
+        }
+
+        fn resize(&self, new_size: (u32, u32)) {}
+    }
+}
+
+/// An openGL vertex.
+#[derive(Copy, Clone)]
+struct Vertex {
+    a_position: [f32; 2],
+    a_texCoord: [f32; 2],
+}
+
+impl Vertex {
+    fn new(position: [f32; 2], tex_coords: [f32; 2]) -> Self {
+        Self {
+            a_position: position,
+            a_texCoord: tex_coords,
+        }
+    }
+}
+
+implement_vertex!(Vertex, a_position, a_texCoord);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RotationStyle {
+    AllAround,
+    LeftRight,
+    DontRotate,
+}
+
+impl RotationStyle {
+    pub fn from_str(input: &str) -> Result<RotationStyle, ()> {
+        match input {
+            "all around" => Ok(RotationStyle::AllAround),
+            "left-right" => Ok(RotationStyle::LeftRight),
+            "don't rotate" => Ok(RotationStyle::DontRotate),
+            _ => Err(()),
+        }
+    }
+    pub fn to_str(self) -> &'static str {
+        match self {
+            RotationStyle::AllAround => "RotationStyle::AllAround",
+            RotationStyle::LeftRight => "RotationStyle::LeftRight",
+            RotationStyle::DontRotate => "RotationStyle::DontRotate",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum VideoState {
+    On,
+    Off,
+    OnFlipped,
+}
+
+impl VideoState {
+    pub fn from_str(input: &str) -> Result<VideoState, ()> {
+        match input {
+            "on" => Ok(VideoState::On),
+            "off" => Ok(VideoState::Off),
+            "on-flipped" => Ok(VideoState::OnFlipped),
+            _ => Err(()),
+        }
+    }
+    pub fn to_str(&self) -> &str {
+        match self {
+            VideoState::Off => "VideoState::Off",
+            VideoState::On => "VideoState::On",
+            VideoState::OnFlipped => "VideoState::OnFlipped",
+        }
+    }
+}
+
+/// A value, for a variable, list, or something else.
+///
+/// This can represent either a number or a string.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Num(f64),
+    String(String),
+    Bool(bool),
+    Null,
+}
+
+// Implement hash for value.
+impl Hash for Value {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Num(x) => (*x as i32).hash(state),
+            Value::String(x) => x.hash(state),
+            Value::Bool(x) => x.hash(state),
+            Value::Null => {}
+        }
+    }
+}
+
+impl Value {
+    fn to_list_index(&self, length: usize, acceptAll: bool) -> Result<usize, ()> {
+        if let Value::String(x) = &self {
+            if x == "all" {
+                if acceptAll {
+                    unimplemented!("Return all not implemented yet");
+                } else {
+                    return Err(());
+                }
+            }
+            if x == "last" {
+                if length > 0 {
+                    return Ok(length);
+                }
+                return Err(());
+            }
+            if x == "random" || x == "any" {
+                if length > 0 {
+                    return Ok(generate_random(1.into(), length.into()).into());
+                }
+                return Err(());
+            }
+        }
+        let index: usize = Number(self) as usize;
+        if index < 1 || index > length {
+            return Err(());
+        }
+        Ok(index)
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        if self.partial_cmp(other) == Some(std::cmp::Ordering::Equal) {
+            return true;
+        }
+        false
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        use std::cmp::Ordering as O;
+
+        let mut n1 = Number(self);
+        let mut n2 = Number(other);
+
+        if let Value::String(x) = self {
+            if x.trim().is_empty() {
+                n1 = f64::NAN;
+            }
+        }
+        if let Value::String(x) = other {
+            if x.trim().is_empty() {
+                n2 = f64::NAN;
+            }
+        }
+
+        if n1.is_nan() || n2.is_nan() {
+            let s1 = String(self).to_lowercase();
+            let s2 = String(other).to_lowercase();
+            return Some(s1.cmp(&s2));
+        }
+
+        if n1.is_infinite() && n2.is_infinite() {
+            return Some(O::Equal);
+        }
+
+        if n1 > n2 {
+            return Some(O::Greater);
+        } else if n1 < n2 {
+            return Some(O::Less);
+        }
+        Some(O::Equal)
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Self::Num(0.0)
+    }
+}
+
+/// A macro to convert Values to numbers
+macro_rules! value_into {
+    ($t:ident) => {
+        impl From<Value> for $t {
+            fn from(val: Value) -> $t {
+                match val {
+                    Value::String(_) => 0 as $t,
+                    Value::Bool(x) => match x {
+                        true => 1 as $t,
+                        false => 0 as $t,
+                    },
+                    Value::Null => 0 as $t,
+                    Value::Num(x) => {
+                        if x.is_nan() {
+                            return 0 as $t;
+                        }
+                        x as $t
+                    }
+                }
+            }
+        }
+    };
+}
+
+/// Cast a value into a number, following javascript's casting rules.
+fn Number(input: &Value) -> f64 {
+    match input {
+        Value::Num(x) => *x,
+        Value::Null => 0.0,
+        Value::Bool(x) => match x {
+            true => 1.0,
+            false => 0.0,
+        },
+        Value::String(x) => x.parse().unwrap_or(f64::NAN),
+    }
+}
+
+fn toNumber(input: &Value) -> f64 {
+    if let Value::Num(x) = input {
+        if x.is_nan() {
+            return 0.0;
+        }
+        return *x;
+    }
+
+    let n = Number(input);
+    if n.is_nan() {
+        return 0.0;
+    }
+    n
+}
+
+fn String(input: &Value) -> String {
+    match input {
+        Value::String(x) => x.clone(),
+        Value::Null => String::from("null"),
+        Value::Bool(x) => match x {
+            true => String::from("true"),
+            false => String::from("false"),
+        },
+        Value::Num(x) => format!("{}", x), // Possibly make sure this conforms to javascript?
+                                           // https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Number/toString
+    }
+}
+
+value_into!(u32);
+value_into!(u64);
+value_into!(usize);
+value_into!(f32);
+value_into!(f64);
+value_into!(i32);
+
+impl From<f64> for Value {
+    fn from(item: f64) -> Self {
+        Value::Num(item)
+    }
+}
+
+impl From<f32> for Value {
+    /// Convert through the shortest decimal form of the number, so that
+    /// `0.1` stays `0.1` instead of picking up the rounding error of a 32-bit
+    /// float.
+    fn from(item: f32) -> Self {
+        Value::Num(item.to_string().parse().unwrap_or(item.into()))
+    }
+}
+
+impl From<u32> for Value {
+    fn from(item: u32) -> Self {
+        Value::Num(item.into())
+    }
+}
+impl From<i32> for Value {
+    fn from(item: i32) -> Self {
+        Value::Num(item.into())
+    }
+}
+
+impl From<usize> for Value {
+    fn from(item: usize) -> Self {
+        Value::Num(item as f64)
+    }
+}
+
+impl From<String> for Value {
+    fn from(item: String) -> Self {
+        Value::String(item)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(item: &str) -> Self {
+        Value::String(item.to_string())
+    }
+}
+
+impl From<char> for Value {
+    fn from(item: char) -> Self {
+        Value::String(item.to_string())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<Value> for String {
+    fn from(val: Value) -> String {
+        match val {
+            Value::Num(x) => format!("{}", x),
+            Value::String(x) => x,
+            Value::Bool(x) => {
+                if x {
+                    String::from("true")
+                } else {
+                    String::from("false")
+                }
+            }
+            Value::Null => String::new(),
+        }
+    }
+}
+
+impl From<Value> for bool {
+    fn from(val: Value) -> bool {
+        match val {
+            Value::Num(x) => !matches!(x as i32, 0),
+            Value::String(x) => !matches!(&*x.to_lowercase(), "" | "0" | "false"),
+            Value::Bool(x) => x,
+            Value::Null => false,
+        }
+    }
+}
+
+impl std::ops::Add for Value {
+    type Output = Value;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let lhs_num: f64 = self.into();
+        let rhs_num: f64 = rhs.into();
+
+        Value::Num(lhs_num + rhs_num)
+    }
+}
+
+impl std::ops::Sub for Value {
+    type Output = Value;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        let lhs_num: f64 = self.into();
+        let rhs_num: f64 = rhs.into();
+
+        Value::Num(lhs_num - rhs_num)
+    }
+}
+
+impl std::ops::Mul for Value {
+    type Output = Value;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let lhs_num: f64 = self.into();
+        let rhs_num: f64 = rhs.into();
+
+        Value::Num(lhs_num * rhs_num)
+    }
+}
+
+impl std::ops::Div for Value {
+    type Output = Value;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        let lhs_num: f64 = self.into();
+        let rhs_num: f64 = rhs.into();
+
+        Value::Num(lhs_num / rhs_num)
+    }
+}
+
+impl std::ops::Not for Value {
+    type Output = Value;
+
+    fn not(self) -> Self::Output {
+        let x: bool = self.into();
+        Value::Bool(!x)
+    }
+}
+
+impl std::ops::Neg for Value {
+    type Output = Value;
+
+    fn neg(self) -> Self::Output {
+        let num: f64 = self.into();
+        Value::Num(-num)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // write!(f,"{}",self.va)
+        match self {
+            Self::Num(x) => {
+                write!(f, "{}", x)
+            }
+            Self::String(x) => {
+                write!(f, "{}", x)
+            }
+            Self::Null => {
+                write!(f, "")
+            }
+            Self::Bool(x) => {
+                write!(f, "{}", x)
+            }
+        }
+    }
+}
+
+pub struct SpriteBuilder {
+    visible: bool,
+    x: f32,
+    y: f32,
+    size: f32,
+    direction: f32,
+    draggable: bool,
+    rotation_style: RotationStyle,
+    name: String,
+    variables: HashMap<String, (String, Value)>,
+    lists: HashMap<String, (String, Vec<Value>)>,
+    costume: usize,
+    costumes: Vec<Rc<Costume>>,
+    sounds: Vec<Sound>,
+    volume: f32,
+    layer: u32,
+}
+
+impl SpriteBuilder {
+    /// Create a default new SpriteBuilder.
+    pub fn new(name: String) -> Self {
+        Self {
+            visible: true,
+            x: 0.0,
+            y: 0.0,
+            size: 100.0,
+            direction: 90.0,
+            draggable: false,
+            rotation_style: RotationStyle::AllAround,
+            name,
+            variables: HashMap::new(),
+            lists: HashMap::new(),
+            costume: 0,
+            costumes: Vec::new(),
+            sounds: Vec::new(),
+            volume: 100.0,
+            layer: 999,
+        }
+    }
+
+    pub fn build(self) -> Sprite {
+        Sprite {
+            visible: self.visible,
+            x: self.x,
+            y: self.y,
+            size: self.size,
+            direction: self.direction,
+            draggable: self.draggable,
+            rotation_style: self.rotation_style,
+            name: self.name,
+            lists: self.lists,
+            variables: self.variables,
+            costume: self.costume,
+            sounds: self.sounds,
+            volume: self.volume,
+            costumes: self.costumes,
+            original: None, // we never build a clone
+            uuid: Uuid::new_v4(),
+            to_be_deleted: false,
+            layer: self.layer,
+            effects: HashMap::new(),
+            bubble: None,
+            possible_stop: StopType::None,
+            dragging: false,
+        }
+    }
+
+    pub fn layer(mut self, layer: u32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn position(mut self, x: f32, y: f32) -> Self {
+        self.x = x;
+        self.y = y;
+        self
+    }
+
+    pub fn visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn direction(mut self, direction: f32) -> Self {
+        self.direction = direction;
+        self
+    }
+    pub fn size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn draggable(mut self, draggable: bool) -> Self {
+        self.draggable = draggable;
+        self
+    }
+    pub fn rotation_style(mut self, rotation_style: RotationStyle) -> Self {
+        self.rotation_style = rotation_style;
+        self
+    }
+    pub fn add_variable(mut self, id: String, value: (String, Value)) -> Self {
+        self.variables.insert(id, value);
+        self
+    }
+    pub fn add_list(mut self, id: String, value: (String, Vec<Value>)) -> Self {
+        self.lists.insert(id, value);
+        self
+    }
+    pub fn add_costume(mut self, costume: Rc<Costume>) -> Self {
+        self.costumes.push(costume);
+        self
+    }
+    pub fn add_sound(mut self, sound: Sound) -> Self {
+        self.sounds.push(sound);
+        self
+    }
+    pub fn set_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+    pub fn costume(mut self, costume: usize) -> Self {
+        self.costume = costume;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct Sprite {
+    /// Whether the sprite is visible.  Defaults to true.
+    visible: bool,
+    /// The x-coordinate.  Defaults to 0.
+    x: f32,
+    /// The y-coordinate.  Defaults to 0.
+    y: f32,
+    /// The sprite's size, as a percentage.  Defaults to 100.
+    size: f32,
+    /// The direction of the sprite in degrees clockwise from up.  Defaults to 90.
+    direction: f32,
+    /// Whether the sprite is draggable.  Defaults to false.
+    draggable: bool,
+    /// The rotation style.
+    rotation_style: RotationStyle,
+    /// The name of the sprite.
+    name: String,
+    /// The blocks in the sprite.
+    /// This is currently only 1 stack of blocks,
+    /// but this should change soon.
+    // blocks: Vec<Thread<'a>>,
+    /// A list of variables for the sprite
+    variables: HashMap<String, (String, Value)>,
+    lists: HashMap<String, (String, Vec<Value>)>,
+    /// The current costume. This is an index to the costumes attribute, which
+    /// is itself an index!.
+    costume: usize,
+    /// The costumes of the sprite.  These are shared with the program and
+    /// with any clones of the sprite.
+    costumes: Vec<Rc<Costume>>,
+
+    /// A list of sounds belonging to this sprite.
+    sounds: Vec<Sound>,
+
+    /// The volume of this sprite.
+    volume: f32,
+
+    /// The sprite this sprite is a clone of, or `None` if it is not a clone.
+    ///
+    /// This is always the original sprite, even for clones of clones. It
+    /// influences whether clone blocks can run.
+    original: Option<Rc<Mutex<Sprite>>>,
+
+    uuid: Uuid,
+    to_be_deleted: bool,
+    /// The current layer of the sprite. Sprites with higher layers are
+    /// displayed on top of sprites with lower layers.
+    layer: u32,
+
+    /// The sprite effects
+    effects: HashMap<Effect, f32>,
+    /// The speech or thought bubble next to the sprite, if any.
+    bubble: Option<Bubble>,
+    /// Whether scripts in this sprite need to stop, and what type
+    possible_stop: StopType,
+    /// Whether the sprite is being dragged with the mouse.  Scripts cannot
+    /// move it while it is.
+    dragging: bool,
+}
+
+impl Sprite {
+    fn set_costume(&mut self, mut index: f32) {
+        // round the index to a whole number
+        index = index.round();
+
+        if index.is_infinite() || index.is_nan() {
+            index = 0.0;
+        }
+
+        if self.costumes.is_empty() {
+            return;
+        }
+
+        // wrap the index around, like scratch, so it is always valid
+        index = wrap_clamp(index, 0.0, self.costumes.len() as f32 - 1.0);
+
+        self.costume = index as usize;
+    }
+
+    /// Show a bubble with a message, replacing any bubble already shown, and
+    /// return the id of the new bubble.  An empty message clears the bubble,
+    /// and like Scratch, hidden sprites do not get a bubble at all.
+    fn show_bubble(&mut self, kind: BubbleKind, message: Value) -> Uuid {
+        let usage = Uuid::new_v4();
+
+        // Like Scratch, numbers are rounded to two decimal places, and then
+        // shown like any other number, so 1.5 stays 1.5.  Adding 0 turns -0
+        // into 0.
+        let message = match message {
+            Value::Num(n) => {
+                let rounded = (n * 100.0).round() / 100.0;
+                Value::Num(if rounded.is_finite() {
+                    rounded + 0.0
+                } else {
+                    n
+                })
+            }
+            message => message,
+        };
+        let text: String = String(&message)
+            .chars()
+            .take(Bubble::MAX_CHARACTERS)
+            .collect();
+
+        self.bubble = if text.is_empty() || !self.visible {
+            None
+        } else {
+            match self.bubble.take() {
+                // Keep the rendered bubble if only the id changes.
+                Some(bubble) if bubble.kind == kind && bubble.text == text => {
+                    Some(Bubble { usage, ..bubble })
+                }
+                _ => Some(Bubble::new(kind, text, usage)),
+            }
+        };
+
+        usage
+    }
+
+    fn clone(&self) -> Self {
+        Self {
+            visible: self.visible,
+            x: self.x,
+            y: self.y,
+            size: self.size,
+            direction: self.direction,
+            draggable: self.draggable,
+            rotation_style: self.rotation_style,
+            name: self.name.clone(),
+            variables: self.variables.clone(),
+            lists: self.lists.clone(),
+            costume: self.costume,
+            costumes: self.costumes.clone(),
+            sounds: self.sounds.clone(),
+            volume: self.volume,
+            original: self.original.clone(),
+            uuid: Uuid::new_v4(),
+            to_be_deleted: self.to_be_deleted,
+            layer: self.layer,
+            effects: self.effects.clone(),
+            bubble: None,
+            possible_stop: StopType::None,
+            dragging: false,
+        }
+    }
+
+    /// Whether this sprite is a clone.
+    fn is_clone(&self) -> bool {
+        self.original.is_some()
+    }
+
+    /// Get the rendered direction. 0 is forward, 90 is up, 180 backwards, 270
+    /// down, etc.
+    ///
+    /// Formula from <https://math.stackexchange.com/questions/1589793/a-formula-to-convert-a-counter-clockwise-angle-to-clockwise-angle-with-an-offset>
+    fn get_rendered_direction(&self) -> f32 {
+        (-self.direction + 90.0).rem_euclid(360.0)
+    }
+
+    /// Get the rendered direction and the x and y scale, taking the rotation
+    /// style into account.  "left-right" sprites never rotate, but are
+    /// mirrored when facing left; "don't rotate" sprites ignore direction.
+    fn rendered_direction_and_scale(&self) -> (f32, [f32; 2]) {
+        let scale = self.size / 100.0;
+        match self.rotation_style {
+            RotationStyle::AllAround => (self.get_rendered_direction(), [scale, scale]),
+            RotationStyle::LeftRight if self.direction < 0.0 => (0.0, [-scale, scale]),
+            RotationStyle::LeftRight | RotationStyle::DontRotate => (0.0, [scale, scale]),
+        }
+    }
+
+    /// The bounding box of the current costume on the stage, after it has been
+    /// rotated and scaled.  This is `None` if the sprite has no costumes.
+    pub fn bounds(&self) -> Option<Rectangle> {
+        Some(Rectangle::of_matrix(&self.model_matrix()?))
+    }
+
+    /// The model matrix of the current costume, taking position, direction,
+    /// size and rotation style into account.  This is `None` if the sprite
+    /// has no costumes.
+    fn model_matrix(&self) -> Option<[[f32; 4]; 4]> {
+        let costume = self.costumes.get(self.costume)?;
+        let (direction, scale) = self.rendered_direction_and_scale();
+
+        Some(model_matrix(costume, (self.x, self.y), direction, scale))
+    }
+
+    /// The sprite's costume as it is placed on the stage, for collision
+    /// tests.  This is `None` if the sprite is hidden or has no costumes, as
+    /// then it touches nothing.
+    fn collision_shape(&self) -> Option<CollisionShape> {
+        if !self.visible {
+            return None;
+        }
+
+        let costume = self.costumes.get(self.costume)?.clone();
+        let matrix = self.model_matrix()?;
+        let bounds = self.bounds()?;
+
+        Some(CollisionShape {
+            costume,
+            matrix,
+            bounds,
+        })
+    }
+
+    /// Find where the sprite should go when it moves to (x, y), so that part of
+    /// it always stays on the stage.
+    ///
+    /// This is Scratch's `getFencedPositionOfDrawable`.
+    fn fenced_position(&self, x: f32, y: f32) -> (f32, f32) {
+        /// How much of the sprite is kept on the stage, at most.
+        const FENCE_WIDTH: f32 = 15.0;
+
+        let Some(bounds) = self.bounds() else {
+            return (x, y);
+        };
+        let (mut x, mut y) = (x, y);
+        let (dx, dy) = (x - self.x, y - self.y);
+        let inset = (bounds.width().min(bounds.height()) / 2.0).floor();
+
+        let half_width: f32 = SCRATCH_HALF_WIDTH.into();
+        let sx = half_width - FENCE_WIDTH.min(inset);
+        if bounds.right + dx < -sx {
+            x = (self.x - (sx + bounds.right)).ceil();
+        } else if bounds.left + dx > sx {
+            x = (self.x + (sx - bounds.left)).floor();
+        }
+
+        let half_height: f32 = SCRATCH_HALF_HEIGHT.into();
+        let sy = half_height - FENCE_WIDTH.min(inset);
+        if bounds.top + dy < -sy {
+            y = (self.y - (sy + bounds.top)).ceil();
+        } else if bounds.bottom + dy > sy {
+            y = (self.y + (sy - bounds.bottom)).floor();
+        }
+
+        (x, y)
+    }
+
+    /// Find where the sprite should go when it moves to (x, y), so that all
+    /// of it is on the stage.
+    ///
+    /// This is Scratch's `keepInFence`.
+    fn keep_in_fence(&self, x: f32, y: f32) -> (f32, f32) {
+        let Some(bounds) = self.bounds() else {
+            return (x, y);
+        };
+        let bounds = bounds.translate(x - self.x, y - self.y);
+
+        let half_width: f32 = SCRATCH_HALF_WIDTH.into();
+        let half_height: f32 = SCRATCH_HALF_HEIGHT.into();
+
+        let (mut dx, mut dy) = (0.0, 0.0);
+        if bounds.left < -half_width {
+            dx += -half_width - bounds.left;
+        }
+        if bounds.right > half_width {
+            dx += half_width - bounds.right;
+        }
+        if bounds.top > half_height {
+            dy += half_height - bounds.top;
+        }
+        if bounds.bottom < -half_height {
+            dy += -half_height - bounds.bottom;
+        }
+
+        (x + dx, y + dy)
+    }
+
+    /// Move the sprite.  If `fencing` is on, the sprite is not allowed to
+    /// leave the stage completely.
+    ///
+    /// Like in Scratch, a sprite being dragged does not move, so that scripts
+    /// do not fight the mouse.
+    fn set_xy(&mut self, x: f32, y: f32, fencing: bool) {
+        if !self.dragging {
+            self.force_xy(x, y, fencing);
+        }
+    }
+
+    /// Move the sprite, even if it is being dragged.
+    fn force_xy(&mut self, x: f32, y: f32, fencing: bool) {
+        let (x, y) = if fencing {
+            self.fenced_position(x, y)
+        } else {
+            (x, y)
+        };
+        self.x = x;
+        self.y = y;
+    }
+
+    /// Set the direction, wrapping it into the range (-180, 180] like Scratch.
+    /// Directions that are not finite are ignored.
+    fn set_direction(&mut self, direction: f32) {
+        if direction.is_finite() {
+            self.direction = wrap_clamp(direction, -179.0, 180.0);
+        }
+    }
+}
+
+/// An axis aligned rectangle on the stage, in scratch coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rectangle {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+impl Rectangle {
+    /// The smallest rectangle containing all the points.
+    fn around(points: &[(f32, f32)]) -> Self {
+        points.iter().fold(
+            Rectangle {
+                left: f32::INFINITY,
+                right: f32::NEG_INFINITY,
+                top: f32::NEG_INFINITY,
+                bottom: f32::INFINITY,
+            },
+            |rect, &(x, y)| Rectangle {
+                left: rect.left.min(x),
+                right: rect.right.max(x),
+                top: rect.top.max(y),
+                bottom: rect.bottom.min(y),
+            },
+        )
+    }
+
+    /// The rectangle around a costume placed on the stage with a model
+    /// matrix.
+    fn of_matrix(matrix: &[[f32; 4]; 4]) -> Self {
+        let corners = [(-0.5, 0.5), (0.5, 0.5), (0.5, -0.5), (-0.5, -0.5)]
+            .map(|corner| transform_point(matrix, corner));
+
+        Self::around(&corners)
+    }
+
+    /// The whole stage.
+    fn stage() -> Self {
+        let (half_width, half_height) = (
+            f32::from(SCRATCH_HALF_WIDTH),
+            f32::from(SCRATCH_HALF_HEIGHT),
+        );
+        Rectangle {
+            left: -half_width,
+            right: half_width,
+            top: half_height,
+            bottom: -half_height,
+        }
+    }
+
+    /// The points with whole number coordinates in and around the
+    /// rectangle, which collision tests check.
+    fn points(&self) -> impl Iterator<Item = (f32, f32)> {
+        let (left, right) = (self.left.floor() as i32, self.right.ceil() as i32);
+        let (bottom, top) = (self.bottom.floor() as i32, self.top.ceil() as i32);
+
+        (bottom..=top).flat_map(move |y| (left..=right).map(move |x| (x as f32, y as f32)))
+    }
+
+    pub fn width(&self) -> f32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> f32 {
+        self.top - self.bottom
+    }
+
+    /// Move the rectangle.
+    fn translate(&self, dx: f32, dy: f32) -> Self {
+        Rectangle {
+            left: self.left + dx,
+            right: self.right + dx,
+            top: self.top + dy,
+            bottom: self.bottom + dy,
+        }
+    }
+
+    /// The overlap of two rectangles, or `None` if they do not overlap.
+    fn intersection(&self, other: &Rectangle) -> Option<Self> {
+        let intersection = Rectangle {
+            left: self.left.max(other.left),
+            right: self.right.min(other.right),
+            top: self.top.min(other.top),
+            bottom: self.bottom.max(other.bottom),
+        };
+
+        (intersection.left <= intersection.right && intersection.bottom <= intersection.top)
+            .then_some(intersection)
+    }
+}
+
+/// Build the model matrix that places a costume on the stage.
+///
+/// The costume's rotation center is put at `position`, then the costume is
+/// scaled by `scale` and rotated `direction` degrees counterclockwise around
+/// it.  Sprites, stamps, the stage and bounds all use this so they agree.
+fn model_matrix(
+    costume: &Costume,
+    (x, y): (f32, f32),
+    direction: f32,
+    [scale_x, scale_y]: [f32; 2],
+) -> [[f32; 4]; 4] {
+    let (width, height) = costume.size();
+    let (center_x, center_y) = costume.center();
+    // How far the middle of the costume is from the rotation center.
+    let (offset_x, offset_y) = (width / 2.0 - center_x, center_y - height / 2.0);
+
+    let (sin, cos) = direction.to_radians().sin_cos();
+
+    [
+        [cos * scale_x * width, sin * scale_x * width, 0.0, 0.0],
+        [-sin * scale_y * height, cos * scale_y * height, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [
+            cos * scale_x * offset_x - sin * scale_y * offset_y + x,
+            sin * scale_x * offset_x + cos * scale_y * offset_y + y,
+            0.0,
+            1.0,
+        ],
+    ]
+}
+
+/// Apply a model matrix to a point on the unit square costumes are drawn on.
+fn transform_point(matrix: &[[f32; 4]; 4], (u, v): (f32, f32)) -> (f32, f32) {
+    (
+        matrix[0][0] * u + matrix[1][0] * v + matrix[3][0],
+        matrix[0][1] * u + matrix[1][1] * v + matrix[3][1],
+    )
+}
+
+/// Find the point on the unit square that a model matrix moves to a point on
+/// the stage.  This is `None` if the matrix squashes the costume flat.
+fn inverse_transform_point(matrix: &[[f32; 4]; 4], (x, y): (f32, f32)) -> Option<(f32, f32)> {
+    let determinant = matrix[0][0] * matrix[1][1] - matrix[1][0] * matrix[0][1];
+    if determinant == 0.0 {
+        return None;
+    }
+
+    let (dx, dy) = (x - matrix[3][0], y - matrix[3][1]);
+    Some((
+        (matrix[1][1] * dx - matrix[1][0] * dy) / determinant,
+        (matrix[0][0] * dy - matrix[0][1] * dx) / determinant,
+    ))
+}
+
+/// Wrap a number so that it is in the range [min, max], as in Scratch's
+/// `MathUtil.wrapClamp`.
+fn wrap_clamp(n: f32, min: f32, max: f32) -> f32 {
+    let range = max - min + 1.0;
+    n - ((n - min) / range).floor() * range
+}
+
+/// The compiled shaders, one for each combination of graphic effects and draw
+/// mode that has been drawn so far.
+struct ShaderCache {
+    programs: HashMap<(Vec<Effect>, DrawMode), glium::Program>,
+}
+
+impl ShaderCache {
+    fn new() -> Self {
+        Self {
+            programs: HashMap::new(),
+        }
+    }
+
+    /// Get the shader for a set of effects and a draw mode, compiling it the
+    /// first time that combination is used.
+    fn get<'a>(
+        &mut self,
+        window: &SDL2Facade,
+        effects: impl Iterator<Item = &'a Effect>,
+        draw_mode: DrawMode,
+    ) -> &glium::Program {
+        let mut effects: Vec<Effect> = effects.copied().collect();
+        effects.sort();
+
+        self.programs
+            .entry((effects, draw_mode))
+            .or_insert_with_key(|(effects, draw_mode)| {
+                let mut defines = effects
+                    .iter()
+                    .map(|effect| effect.define_string())
+                    .collect::<Vec<_>>();
+
+                defines.push(draw_mode.define_string());
+
+                let define_string = format!("#version 140\n{}", defines.join("\n"));
+
+                glium::Program::from_source(
+                    window,
+                    &format!("{}\n{}", define_string, Program::get_vertex_shader()),
+                    &format!("{}\n{}", define_string, Program::get_fragment_shader()),
+                    None,
+                )
+                .unwrap()
+            })
+    }
+}
+
+impl PartialEq for Sprite {
+    fn eq(&self, other: &Self) -> bool {
+        self.visible == other.visible
+            && self.x == other.x
+            && self.y == other.y
+            && self.size == other.size
+            && self.direction == other.direction
+            && self.draggable == other.draggable
+            && self.rotation_style == other.rotation_style
+            && self.name == other.name
+            && self.variables == other.variables
+            && self.lists == other.lists
+            && self.costume == other.costume
+            // && self.costumes == other.costumes
+            && self.is_clone() == other.is_clone()
+    }
+}
+
+/// A costume placed on the stage, used to test what a sprite is touching.
+///
+/// Graphic effects are ignored, so ghosted sprites still touch things, like
+/// in Scratch.  Effects that change the shape of a sprite, like whirl, are not
+/// taken into account either.
+struct CollisionShape {
+    costume: Rc<Costume>,
+    matrix: [[f32; 4]; 4],
+    bounds: Rectangle,
+}
+
+impl CollisionShape {
+    /// Whether the costume is opaque at a point on the stage.
+    fn contains(&self, point: (f32, f32)) -> bool {
+        inverse_transform_point(&self.matrix, point)
+            .is_some_and(|point| self.costume.silhouette.is_opaque(point))
+    }
+
+    /// The rectangle around the opaque part of the costume on the stage,
+    /// leaving out any transparent padding.  This is `None` if the costume
+    /// is fully transparent.
+    fn tight_bounds(&self) -> Option<Rectangle> {
+        let extent = self.costume.silhouette.opaque_extent?;
+        let corners = [
+            (extent.left, extent.top),
+            (extent.right, extent.top),
+            (extent.right, extent.bottom),
+            (extent.left, extent.bottom),
+        ]
+        .map(|corner| transform_point(&self.matrix, corner));
+
+        Some(Rectangle::around(&corners))
+    }
+
+    /// Whether two costumes overlap.  Their bounds are compared first, and
+    /// only the points where they overlap are tested, one scratch unit
+    /// apart, like Scratch's `_isTouchingDrawablesCPU`.
+    fn touches(&self, other: &CollisionShape) -> bool {
+        let Some(bounds) = self.bounds.intersection(&other.bounds) else {
+            return false;
+        };
+
+        bounds
+            .points()
+            .any(|point| self.contains(point) && other.contains(point))
+    }
+
+    /// The color of the costume at a point on the stage, or `None` if the
+    /// point is off the costume.
+    fn color_at(&self, point: (f32, f32)) -> Option<[u8; 4]> {
+        self.costume
+            .silhouette
+            .color_at(inverse_transform_point(&self.matrix, point)?)
+    }
+}
+
+/// The pixels of a costume, kept on the CPU for collision tests.
+struct Silhouette {
+    width: u32,
+    height: u32,
+    /// RGBA pixels, row by row from the top left.
+    pixels: Vec<u8>,
+    /// The rectangle around the opaque pixels on the unit square, or `None`
+    /// if no pixel is opaque.
+    opaque_extent: Option<Rectangle>,
+}
+
+impl Silhouette {
+    fn new(image: &image::RgbaImage) -> Self {
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        let opaque_extent = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[3] > 0)
+            .map(|(column, row, _)| Rectangle {
+                left: column as f32 / width - 0.5,
+                right: (column + 1) as f32 / width - 0.5,
+                top: 0.5 - row as f32 / height,
+                bottom: 0.5 - (row + 1) as f32 / height,
+            })
+            .reduce(|extent, pixel| Rectangle {
+                left: extent.left.min(pixel.left),
+                right: extent.right.max(pixel.right),
+                top: extent.top.max(pixel.top),
+                bottom: extent.bottom.min(pixel.bottom),
+            });
+
+        Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.as_raw().clone(),
+            opaque_extent,
+        }
+    }
+
+    /// The color of the pixel at a point on the unit square, or `None` if the
+    /// point is off the costume.
+    fn color_at(&self, (u, v): (f32, f32)) -> Option<[u8; 4]> {
+        if !(-0.5..0.5).contains(&u) || !(-0.5..0.5).contains(&v) {
+            return None;
+        }
+
+        let column = (((u + 0.5) * self.width as f32) as u32).min(self.width - 1);
+        let row = (((0.5 - v) * self.height as f32) as u32).min(self.height - 1);
+        let index = ((row * self.width + column) * 4) as usize;
+
+        self.pixels
+            .get(index..index + 4)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+    }
+
+    /// Whether the costume is opaque at a point on the unit square.
+    fn is_opaque(&self, point: (f32, f32)) -> bool {
+        self.color_at(point)
+            .is_some_and(|[_, _, _, alpha]| alpha > 0)
+    }
+}
+
+/// Find the color of the stage at a point, from layers of costumes listed
+/// from the top down with their opacity.  The stage is white below
+/// everything.  This is Scratch's `sampleColor3b`.
+fn sample_color(layers: &[(CollisionShape, f32)], point: (f32, f32)) -> [u8; 3] {
+    blend_colors(
+        layers
+            .iter()
+            .filter_map(|(shape, opacity)| Some((shape.color_at(point)?, *opacity))),
+    )
+}
+
+/// Blend colors listed from the top down with their opacity over the white
+/// stage.
+fn blend_colors(colors: impl IntoIterator<Item = ([u8; 4], f32)>) -> [u8; 3] {
+    let mut color = [0.0; 3];
+    // How much of the layers below still shows through.
+    let mut remaining = 1.0;
+
+    for ([red, green, blue, alpha], opacity) in colors {
+        let alpha = f32::from(alpha) / 255.0 * opacity;
+        for (channel, value) in color.iter_mut().zip([red, green, blue]) {
+            *channel += f32::from(value) * alpha * remaining;
+        }
+
+        remaining *= 1.0 - alpha;
+        if remaining <= 0.0 {
+            break;
+        }
+    }
+
+    color.map(|channel| (channel + 255.0 * remaining).round() as u8)
+}
+
+/// Whether a color on the stage matches a color in a touching color block.
+/// Like Scratch, only the top 5 bits of red and green, and the top 4 bits of
+/// blue, are compared.
+fn color_matches(
+    [red, green, blue]: [u8; 3],
+    [other_red, other_green, other_blue]: [u8; 3],
+) -> bool {
+    (red & 0b11111000) == (other_red & 0b11111000)
+        && (green & 0b11111000) == (other_green & 0b11111000)
+        && (blue & 0b11110000) == (other_blue & 0b11110000)
+}
+
+/// Whether a pixel of a sprite matches the color of the sprite in a color is
+/// touching color block.  Like Scratch, the top 6 bits of each channel are
+/// compared.
+fn mask_matches(
+    [red, green, blue, alpha]: [u8; 4],
+    [mask_red, mask_green, mask_blue]: [u8; 3],
+) -> bool {
+    alpha > 0
+        && (red & 0b11111100) == (mask_red & 0b11111100)
+        && (green & 0b11111100) == (mask_green & 0b11111100)
+        && (blue & 0b11111100) == (mask_blue & 0b11111100)
+}
+
+/// How opaque something is with the ghost effect applied, from 0 to 1.
+fn opacity(effects: &HashMap<Effect, f32>) -> f32 {
+    1.0 - effects
+        .get(&Effect::Ghost)
+        .copied()
+        .unwrap_or(0.0)
+        .clamp(0.0, 100.0)
+        / 100.0
+}
+
+/// Parse a color like `#ff8000`, or the short form `#f80`, into a number like
+/// `0xff8000`.  This is `None` if the color is not valid hex.
+pub fn hex_color_to_number(hex: &str) -> Option<u32> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    let hex = match hex.len() {
+        3 => hex.chars().flat_map(|digit| [digit, digit]).collect(),
+        6 => hex.to_string(),
+        _ => return None,
+    };
+
+    // `from_str_radix` would also accept a sign.
+    if !hex.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+/// Convert a color to red, green and blue, like Scratch's
+/// `Cast.toRgbColorList`.  Colors are either numbers like `0xff8000`, or
+/// strings like `#ff8000`.
+fn color_to_rgb(color: &Value) -> [u8; 3] {
+    let color = match color {
+        Value::String(hex) if hex.starts_with('#') => hex_color_to_number(hex).unwrap_or(0),
+        // Scratch uses 32 bit integers, so large numbers wrap around.
+        color => toNumber(color) as i64 as u32,
+    };
+
+    let [_, red, green, blue] = color.to_be_bytes();
+    [red, green, blue]
+}
+
+/// A costume or backdrop
+pub struct Costume {
+    name: String,
+    /// The point the costume rotates around, in costume pixels from the top
+    /// left.  `None` means the middle of the costume.
+    rotation_center: Option<(f32, f32)>,
+    /// How many costume pixels make up one scratch unit.
+    bitmap_resolution: f32,
+    /// The costume rasterized at `scale`.
+    texture: Rc<glium::texture::Texture2d>,
+    /// The pixels of `texture`, for collision tests.
+    silhouette: Silhouette,
+    /// The source of vector costumes, used to rasterize them again at other
+    /// resolutions.
+    svg: Option<String>,
+    /// Vector costumes rasterized at different resolutions, by mip level.
+    /// Level `n` is rasterized at a scale of 2^n.
+    mips: RefCell<HashMap<i32, Rc<glium::texture::Texture2d>>>,
+    vertices: glium::VertexBuffer<Vertex>,
+    indices: glium::index::NoIndices,
+    path: PathBuf,
+    scale: f32,
+}
+
+impl std::fmt::Debug for Costume {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Costume")
+            .field("name", &self.name)
+            .field("rotation_center", &self.rotation_center)
+            .field("bitmap_resolution", &self.bitmap_resolution)
+            .field("path", &self.path)
+            .field("scale", &self.scale)
+            .finish()
+    }
+}
+
+impl Costume {
+    fn new(
+        window: &SDL2Facade,
+        name: String,
+        path: PathBuf,
+        scale: f32,
+    ) -> Result<Self, &'static str> {
+        if path.extension().is_some_and(|extension| extension == "svg") {
+            let svg = fs::read_to_string(&path).or(Err("Cannot read file"))?;
+            let image = rasterize_svg(&svg, scale)?;
+
+            let mut costume = Self::from_image(window, name, &image, path, scale)?;
+            costume.svg = Some(svg);
+            Ok(costume)
+        } else {
+            let image = image::open(&path)
+                .or(Err("Not a readable image file"))?
+                .to_rgba8();
+
+            Self::from_image(window, name, &image, path, scale)
+        }
+    }
+
+    /// Load a costume like `new`, but fall back to an empty costume if it
+    /// cannot be loaded, so that costume numbers still line up with the
+    /// project.
+    pub fn load(window: &SDL2Facade, name: String, path: PathBuf, scale: f32) -> Self {
+        Self::new(window, name.clone(), path.clone(), scale).unwrap_or_else(|error| {
+            eprintln!(
+                "Could not load costume `{name}` from {}: {error}",
+                path.display()
+            );
+
+            let image = image::RgbaImage::new(1, 1);
+            Self::from_image(window, name, &image, path, scale)
+                .expect("Creating an empty texture will not fail")
+        })
+    }
+
+    /// Create a costume from svg source that is built into the player, such
+    /// as the icons of the controls overlay.
+    fn from_svg_str(window: &SDL2Facade, name: String, svg: &str) -> Result<Self, &'static str> {
+        let image = rasterize_svg(svg, 1.0)?;
+
+        let mut costume = Self::from_image(window, name, &image, PathBuf::new(), 1.0)?;
+        costume.svg = Some(svg.to_string());
+        Ok(costume)
+    }
+
+    fn from_image(
+        window: &SDL2Facade,
+        name: String,
+        image: &image::RgbaImage,
+        path: PathBuf,
+        scale: f32,
+    ) -> Result<Self, &'static str> {
+        let texture = get_texture_from_image(window, image)?;
+
+        // A unit square; `model_matrix` stretches it to the size of the costume.
+        let vertices_vec = Program::rect([-0.5, 0.5], [0.5, -0.5]);
+        let vertices = glium::VertexBuffer::new(window, &vertices_vec).unwrap();
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+
+        Ok(Self {
+            name,
+            rotation_center: None,
+            bitmap_resolution: 1.0,
+            texture: Rc::new(texture),
+            silhouette: Silhouette::new(image),
+            svg: None,
+            mips: RefCell::new(HashMap::new()),
+            vertices,
+            indices,
+            path,
+            scale,
+        })
+    }
+
+    /// Set the point the costume rotates around, in costume pixels from the
+    /// top left, as in `rotationCenterX` and `rotationCenterY`.
+    pub fn rotation_center(mut self, x: f32, y: f32) -> Self {
+        self.rotation_center = Some((x, y));
+        self
+    }
+
+    /// Set how many costume pixels make up one scratch unit, as in
+    /// `bitmapResolution`.
+    pub fn bitmap_resolution(mut self, bitmap_resolution: f32) -> Self {
+        self.bitmap_resolution = bitmap_resolution;
+        self
+    }
+
+    /// The size of the costume at 100% size, in scratch units.
+    fn size(&self) -> (f32, f32) {
+        let (width, height) = self.texture.dimensions();
+        let pixels_per_unit = self.scale * self.bitmap_resolution;
+        (
+            width as f32 / pixels_per_unit,
+            height as f32 / pixels_per_unit,
+        )
+    }
+
+    /// The rotation center in scratch units from the top left.
+    fn center(&self) -> (f32, f32) {
+        match self.rotation_center {
+            Some((x, y)) => (x / self.bitmap_resolution, y / self.bitmap_resolution),
+            None => {
+                let (width, height) = self.size();
+                (width / 2.0, height / 2.0)
+            }
+        }
+    }
+
+    /// Get a texture with about the resolution the costume is shown at on
+    /// screen, where `screen_scale` is screen pixels per costume unit.
+    ///
+    /// Like Scratch's SVGSkin, vector costumes are rasterized again at the
+    /// next power of two scale, and each of these mip levels is kept for
+    /// reuse.  Bitmap costumes always use their own texture.
+    fn texture(&self, window: &SDL2Facade, screen_scale: f32) -> Rc<glium::texture::Texture2d> {
+        /// The largest texture that will be rasterized.
+        const MAX_TEXTURE_DIMENSION: f32 = 2048.0;
+        /// The smallest mip level, for a scale of 1/256.
+        const MIN_MIP_LEVEL: f32 = -8.0;
+
+        let Some(svg) = &self.svg else {
+            return self.texture.clone();
+        };
+
+        let (width, height) = self.size();
+        let max_scale = MAX_TEXTURE_DIMENSION / width.max(height).max(1.0);
+        // (0.5, 1] uses the 1x texture, (1, 2] the 2x texture, and so on.
+        let mip_level = screen_scale.min(max_scale).log2().ceil().max(MIN_MIP_LEVEL) as i32;
+        let mip_scale = 2f32.powi(mip_level);
+
+        if mip_scale == self.scale {
+            return self.texture.clone();
+        }
+
+        self.mips
+            .borrow_mut()
+            .entry(mip_level)
+            .or_insert_with(|| match get_texture_from_svg(window, svg, mip_scale) {
+                Ok(texture) => Rc::new(texture),
+                Err(_) => self.texture.clone(),
+            })
+            .clone()
+    }
+
+    fn draw(
+        &self,
+        window: &SDL2Facade,
+        target: &mut glium::Frame,
+        transform: [[f32; 4]; 4],
+        shader: &glium::Program,
+        effects: &HashMap<Effect, f32>,
+    ) {
+        // Work out how many screen pixels each costume unit covers.
+        let (screen_width, screen_height) = target.get_dimensions();
+        let (width, height) = self.size();
+        let screen_scale_x = transform[0][0].hypot(transform[0][1]) / width * screen_width as f32
+            / f32::from(SCRATCH_WIDTH);
+        let screen_scale_y = transform[1][0].hypot(transform[1][1]) / height * screen_height as f32
+            / f32::from(SCRATCH_HEIGHT);
+        let texture = self.texture(window, screen_scale_x.max(screen_scale_y));
+
+        let mut uniforms = DynamicUniforms::new();
+        uniforms.add("u_modelMatrix", &transform);
+        let binding = Program::ortho_matrix();
+        uniforms.add("u_projectionMatrix", &binding);
+        uniforms.add("u_skin", &*texture);
+
+        let effect_uniform_names: Vec<_> = effects.iter().map(|x| x.0.uniforms()).collect();
+        let values: Vec<_> = effects.iter().map(|x| x.1).collect();
+        let calc_values: Vec<_> = values
+            .iter()
+            .zip(effect_uniform_names)
+            .map(|(val, (name, f))| (name, f(**val)))
+            .collect();
+
+        for (name, val) in &calc_values {
+            uniforms.add(name, val);
+        }
+
+        // let uniforms = uniform! {
+        //     u_modelMatrix: transform,
+        //     u_projectionMatrix: Program::identity_matrix(),
+        //     u_skin: &self.texture,
+        // };
+
+        target
+            .draw(
+                &self.vertices,
+                self.indices,
+                shader,
+                &uniforms,
+                &glium::DrawParameters {
+                    blend: glium::Blend::alpha_blending(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Effect {
+    Color,
+    Fisheye,
+    Whirl,
+    Pixelate,
+    Brightness,
+    Ghost,
+    Mosaic,
+}
+
+/// The ways the shader can draw, as in Scratch's `ShaderManager.DRAW_MODE`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum DrawMode {
+    /// Draw normally, with premultiplied alpha.
+    Default,
+    /// Draw normally, with straight alpha.
+    StraightAlpha,
+    /// Draw every pixel in a single color, for touching tests.
+    Silhouette,
+    /// Draw only the parts matching a color, for color touching tests.
+    ColorMask,
+    /// Draw a pen line.
+    Line,
+    /// Draw a solid background color.
+    Background,
+}
+
+impl DrawMode {
+    fn define_string(&self) -> String {
+        String::from(match self {
+            DrawMode::Default => "#define DRAW_MODE_default",
+            DrawMode::StraightAlpha => "#define DRAW_MODE_straightAlpha",
+            DrawMode::Silhouette => "#define DRAW_MODE_silhouette",
+            DrawMode::ColorMask => "#define DRAW_MODE_colorMask",
+            DrawMode::Line => "#define DRAW_MODE_line",
+            DrawMode::Background => "#define DRAW_MODE_background",
+        })
+    }
+}
+
+impl Effect {
+    fn define_string(&self) -> String {
+        String::from(match self {
+            Effect::Color => "#define ENABLE_color",
+            Effect::Fisheye => "#define ENABLE_fisheye",
+            Effect::Whirl => "#define ENABLE_whirl",
+            Effect::Pixelate => "#define ENABLE_pixelate",
+            Effect::Brightness => "#define ENABLE_brightness",
+            Effect::Ghost => "#define ENABLE_ghost",
+            Effect::Mosaic => "#define ENABLE_mosaic",
+        })
+    }
+
+    fn uniforms(&self) -> (&str, Box<dyn Fn(f32) -> f32>) {
+        match self {
+            Effect::Color => ("u_color", Box::new(|x| (x / 200.0) % 1.0)),
+            Effect::Fisheye => ("u_fisheye", Box::new(|x| 0f32.max((x + 100.0) / 100.0))),
+            Effect::Whirl => ("u_whirl", Box::new(|x| -x * PI / 180.0)),
+            Effect::Pixelate => ("u_pixelate", Box::new(|x| x.abs() / 10.0)),
+            Effect::Brightness => ("u_brightness", Box::new(|x| x.clamp(-100.0, 100.0) / 100.0)),
+            Effect::Ghost => (
+                "u_ghost",
+                Box::new(|x| 1.0 - (0f32.max(x.min(100.0)) / 100.0)),
+            ),
+            Effect::Mosaic => (
+                "u_mosaic",
+                Box::new(|x| {
+                    let x = ((x.abs() + 10.0) / 10.0).round();
+
+                    1f32.max(x.min(512.0))
+                }),
+            ),
+        }
+    }
+}
+
+/// A sound that can be played.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Sound {
+    name: String,
+    format: String,
+    rate: u32,
+    sample_count: u32,
+}
+
+impl Sound {
+    fn new(name: String, format: String, rate: u32, sample_count: u32) -> Self {
+        Self {
+            name,
+            format,
+            rate,
+            sample_count,
+        }
+    }
+}
+
+fn initialize_sounds(stage: Rc<Mutex<Stage>>) {
+    let stage = stage.lock().unwrap();
+
+    for sprite in &stage.sprites {
+        let sprite = sprite.lock().unwrap();
+
+        for sound in &sprite.sounds {
+            let val = Value::from(sound.name.clone());
+            let path = format!("assets/{}/{}.{}", sprite.name, sound.name, sound.format);
+
+            // bind_sound_file(val, path);
+        }
+    }
+
+    for sound in &stage.sounds {
+        // bind_sound_file(
+        //     Value::from(sound.name.clone()),
+        //     format!("assets/Stage/{}.{}", sound.name, sound.format),
+        // );
+    }
+}
+
+/// An empty type that implements future.
+struct EmptyFuture {}
+
+/// A future that always returns `()` immediately.
+impl Future for EmptyFuture {
+    type Output = ();
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        std::task::Poll::Ready(())
+    }
+}
+
+/// Return an empty RawWaker which does nothing.
+///
+/// See https://os.phil-opp.com/async-await/#simple-executor
+fn dummy_raw_waker() -> RawWaker {
+    // A function that does nothing.
+    fn no_op(_: *const ()) {}
+
+    // the clone function just creates a new RawWaker. This is fine because the
+    // RawWaker does nothing.
+    fn clone(_: *const ()) -> RawWaker {
+        dummy_raw_waker()
+    }
+
+    // Create a vtable with the clone function, and no_op for wake and drop.
+    let vtable = &RawWakerVTable::new(clone, no_op, no_op, no_op);
+
+    // Create a new RawWaker.
+    RawWaker::new(std::ptr::null::<()>(), vtable)
+}
+
+/// Return an empty waker.
+fn dummy_waker() -> Waker {
+    unsafe { Waker::from_raw(dummy_raw_waker()) }
+}
+
+async fn yield_fn() {}
+
+/// This is a basic Future for other functions to yield. It returns Pending the
+/// first time it is polled and Ready the second time.
+enum Yield {
+    Start,
+    Middle,
+    End,
+}
+
+impl Future for Yield {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match *self.as_mut() {
+            Yield::Start => {
+                *self = Yield::Middle;
+                Poll::Pending
+            }
+            Yield::Middle => {
+                *self = Yield::End;
+                Poll::Ready(())
+            }
+            Yield::End => {
+                panic!("poll called after Poll::Ready was returned");
+            }
+        }
+    }
+}
+
+/// How long a single frame lasts, as in Scratch's 30 fps.
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 30);
+
+/// A clock that measures how long the project has been running.
+///
+/// Unlike `Instant::now()`, this only advances while the project is running,
+/// so anything measured against it (such as waits) freezes while the project
+/// is paused. A virtual clock never advances by itself; it only moves forward
+/// when `advance` is called, which makes it possible to drive the project
+/// deterministically in tests.
+///
+/// The clock also tells the date blocks what the date is.  This follows the
+/// real date and time zone unless it is pinned with `pin_date`.
+#[derive(Debug, Clone)]
+pub struct RuntimeClock {
+    /// The time accumulated before the clock was last resumed.
+    elapsed: Duration,
+    /// When the clock was last resumed. This is `None` while the clock is
+    /// paused, and always `None` for a virtual clock.
+    resumed_at: Option<Instant>,
+    /// Whether this clock only advances through `advance`.
+    is_virtual: bool,
+    /// The date reported instead of the real one, if any.
+    pinned_date: Option<DateTime<FixedOffset>>,
+}
+
+impl RuntimeClock {
+    /// Create a new clock that follows real time.
+    pub fn new() -> Self {
+        Self {
+            elapsed: Duration::ZERO,
+            resumed_at: Some(Instant::now()),
+            is_virtual: false,
+            pinned_date: None,
+        }
+    }
+
+    /// Create a new clock that only advances when `advance` is called.
+    pub fn new_virtual() -> Self {
+        Self {
+            elapsed: Duration::ZERO,
+            resumed_at: None,
+            is_virtual: true,
+            pinned_date: None,
+        }
+    }
+
+    /// Get the amount of time the project has been running.
+    pub fn now(&self) -> Duration {
+        match self.resumed_at {
+            Some(resumed_at) => self.elapsed + resumed_at.elapsed(),
+            None => self.elapsed,
+        }
+    }
+
+    /// Stop the clock from advancing.
+    pub fn pause(&mut self) {
+        if let Some(resumed_at) = self.resumed_at.take() {
+            self.elapsed += resumed_at.elapsed();
+        }
+    }
+
+    /// Let the clock advance again after it was paused.
+    pub fn resume(&mut self) {
+        if !self.is_virtual && self.resumed_at.is_none() {
+            self.resumed_at = Some(Instant::now());
+        }
+    }
+
+    /// Move the clock forward manually.
+    pub fn advance(&mut self, duration: Duration) {
+        self.elapsed += duration;
+    }
+
+    /// Get the current date and time in the local time zone, or the pinned
+    /// date if there is one.
+    pub fn date(&self) -> DateTime<FixedOffset> {
+        self.pinned_date.unwrap_or_else(|| Local::now().into())
+    }
+
+    /// Always report `date` as the current date and time, so that the date
+    /// blocks give the same answers every time.
+    pub fn pin_date(&mut self, date: DateTime<FixedOffset>) {
+        self.pinned_date = Some(date);
+    }
+}
+
+impl Default for RuntimeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An enum to represent a wait.  This yields once at the beginning,
+/// and then continues to yield until the wait is over.
+///
+/// The wait is measured against the program's `RuntimeClock`.
+enum Wait {
+    Start {
+        clock: Rc<Mutex<RuntimeClock>>,
+        end: Duration,
+    },
+    Middle {
+        clock: Rc<Mutex<RuntimeClock>>,
+        end: Duration,
+    },
+    End,
+}
+
+impl Wait {
+    /// Create a new wait.
+    fn new(clock: Rc<Mutex<RuntimeClock>>, duration: Duration) -> Self {
+        let end = clock.lock().unwrap().now().saturating_add(duration);
+        Self::Start { clock, end }
+    }
+}
+
+impl Future for Wait {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match this {
+            Wait::Start { clock, end } => {
+                let (clock, end) = (clock.clone(), *end);
+                *this = Wait::Middle { clock, end };
+                Poll::Pending
+            }
+            Wait::Middle { clock, end } => {
+                // println!("Wait being polled...");
+                if clock.lock().unwrap().now() >= *end {
+                    *this = Wait::End;
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            }
+            Wait::End => panic!("poll called after Poll::Ready was returned"),
+        }
+    }
+}
+
+/// A thread object. This is a "virtual thread"; that is, it is not run in a
+/// separate thread, but in an async loop.
+struct Thread {
+    /// The function to be called for the thread. This is a generator function
+    /// that can have yields in it.
+    function: Pin<Box<dyn Future<Output = ()>>>,
+    /// Creates a fresh copy of `function`, so that the script can be run
+    /// again from the top once it finishes or is stopped.
+    start_function: Box<dyn Fn() -> Pin<Box<dyn Future<Output = ()>>>>,
+    /// The object that this thread works on. The number represents the index of
+    /// the object in the program vector. If this is None, it represents the
+    /// stage.
+    // obj_index: Option<usize>,
+    running: bool,
+    /// When the thread should start
+    start: StartType,
+
+    thread_uuid: Uuid,
+    sprite_uuid: Option<Uuid>,
+}
+
+impl std::fmt::Debug for Thread {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Thread")
+            .field("running", &self.running)
+            .field("start", &self.start)
+            .finish()
+    }
+}
+
+impl Thread {
+    /// Create a new thread from a function that starts the script.
+    fn new<F: Future<Output = ()> + 'static>(
+        start_function: impl Fn() -> F + 'static, /*, obj_index: Option<usize>*/
+        start: StartType,
+        thread_uuid: Uuid,
+        sprite_uuid: Option<Uuid>,
+    ) -> Thread {
+        let start_function: Box<dyn Fn() -> Pin<Box<dyn Future<Output = ()>>>> =
+            Box::new(move || Box::pin(start_function()));
+        Thread {
+            function: start_function(),
+            start_function,
+            running: false,
+            start, // obj_index,
+            thread_uuid,
+            sprite_uuid,
+        }
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        self.function.as_mut().poll(context)
+    }
+
+    /// Stop the thread and rewind it to the top of its script, ready to be
+    /// started again by its hat block.
+    fn stop(&mut self) {
+        self.running = false;
+        self.function = (self.start_function)();
+    }
+}
+
+/// A button in the controls overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlButton {
+    GreenFlag,
+    Stop,
+}
+
+/// The bar with the green flag and stop buttons drawn over the top left
+/// corner of the stage.
+struct Controls {
+    bar: Costume,
+    green_flag: Costume,
+    stop: Costume,
+}
+
+impl Controls {
+    /// The center of the bar, in scratch coordinates.
+    const BAR_POSITION: (f32, f32) = (-208.0, 166.0);
+    const GREEN_FLAG_POSITION: (f32, f32) = (-222.0, 166.0);
+    const STOP_POSITION: (f32, f32) = (-194.0, 166.0);
+    /// Half the width and height of each button.
+    const BUTTON_HALF_SIZE: f32 = 12.0;
+
+    const BAR_SVG: &'static str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="64" height="28">
+        <rect x="0.5" y="0.5" width="63" height="27" rx="4" fill="#ffffff" fill-opacity="0.85" stroke="#d9d9d9"/>
+    </svg>"##;
+    const GREEN_FLAG_SVG: &'static str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="20">
+        <path d="M4 2v16" stroke="#45993d" stroke-width="2" stroke-linecap="round"/>
+        <path d="M5 3c3-2 6 2 10 0v8c-4 2-7-2-10 0z" fill="#4cbf56" stroke="#45993d" stroke-linejoin="round"/>
+    </svg>"##;
+    const STOP_SVG: &'static str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="20">
+        <polygon points="6,2 14,2 18,6 18,14 14,18 6,18 2,14 2,6" fill="#ec5959" stroke="#b84848" stroke-linejoin="round"/>
+    </svg>"##;
+
+    fn new(window: &SDL2Facade) -> Result<Self, &'static str> {
+        Ok(Self {
+            bar: Costume::from_svg_str(window, String::from("controls"), Self::BAR_SVG)?,
+            green_flag: Costume::from_svg_str(
+                window,
+                String::from("green flag"),
+                Self::GREEN_FLAG_SVG,
+            )?,
+            stop: Costume::from_svg_str(window, String::from("stop"), Self::STOP_SVG)?,
+        })
+    }
+
+    /// Find the button at a position in scratch coordinates.
+    fn button_at(&self, (x, y): (f32, f32)) -> Option<ControlButton> {
+        let is_over = |(button_x, button_y): (f32, f32)| {
+            (x - button_x).abs() <= Self::BUTTON_HALF_SIZE
+                && (y - button_y).abs() <= Self::BUTTON_HALF_SIZE
+        };
+
+        if is_over(Self::GREEN_FLAG_POSITION) {
+            Some(ControlButton::GreenFlag)
+        } else if is_over(Self::STOP_POSITION) {
+            Some(ControlButton::Stop)
+        } else {
+            None
+        }
+    }
+
+    fn draw(&self, window: &SDL2Facade, target: &mut glium::Frame, shader: &glium::Program) {
+        let effects = HashMap::new();
+
+        for (costume, position) in [
+            (&self.bar, Self::BAR_POSITION),
+            (&self.green_flag, Self::GREEN_FLAG_POSITION),
+            (&self.stop, Self::STOP_POSITION),
+        ] {
+            let transform = model_matrix(costume, position, 0.0, [1.0, 1.0]);
+            costume.draw(window, target, transform, shader, &effects);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum BubbleKind {
+    Say,
+    Think,
+}
+
+/// A speech or thought bubble shown next to a sprite, drawn like Scratch's
+/// `TextBubbleSkin`.
+#[derive(Debug)]
+struct Bubble {
+    kind: BubbleKind,
+    text: String,
+    /// The text, word wrapped to fit in the bubble.
+    lines: Vec<String>,
+    /// The width of the longest line.
+    text_width: f32,
+    /// Identifies the block that showed the bubble, so that the "for secs"
+    /// blocks only clear their own bubble.
+    usage: Uuid,
+    /// Whether the bubble is to the right of the sprite.  It moves to the
+    /// other side when it would go off the stage.
+    on_sprite_right: bool,
+    /// The rendered bubble.  This is created when the bubble is first drawn,
+    /// and again whenever it changes sides.
+    costume: Option<Costume>,
+}
+
+impl Bubble {
+    /// The longest message a bubble shows.
+    const MAX_CHARACTERS: usize = 330;
+    const MAX_LINE_WIDTH: f32 = 170.0;
+    const MIN_WIDTH: f32 = 50.0;
+    const STROKE_WIDTH: f32 = 4.0;
+    const PADDING: f32 = 10.0;
+    const CORNER_RADIUS: f32 = 16.0;
+    const TAIL_HEIGHT: f32 = 12.0;
+    const FONT_SIZE: f32 = 14.0;
+    const FONT_HEIGHT_RATIO: f32 = 0.9;
+    const LINE_HEIGHT: f32 = 16.0;
+
+    fn new(kind: BubbleKind, text: String, usage: Uuid) -> Self {
+        let lines = Self::wrap(&text);
+        let text_width = lines
+            .iter()
+            .map(|line| text_width(line, Self::FONT_SIZE, false))
+            .fold(0.0, f32::max)
+            .ceil();
+
+        Self {
+            kind,
+            text,
+            lines,
+            text_width,
+            usage,
+            on_sprite_right: true,
+            costume: None,
+        }
+    }
+
+    /// Split text into lines that fit in the bubble, like scratch-render's
+    /// `TextWrapper`.  Lines break after spaces and at newlines, and words
+    /// too long for a line by themselves are split between characters.
+    fn wrap(text: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut current_line: Option<String> = None;
+
+        for segment in text.split_inclusive('\n') {
+            let required = segment.ends_with('\n');
+
+            for word in segment.trim_end_matches('\n').split_inclusive(' ') {
+                let mut proposed_line = current_line.clone().unwrap_or_default() + word;
+
+                if text_width(&proposed_line, Self::FONT_SIZE, false) > Self::MAX_LINE_WIDTH {
+                    if text_width(word, Self::FONT_SIZE, false) > Self::MAX_LINE_WIDTH {
+                        // Fill up the current line, then go on one character
+                        // at a time.
+                        let mut line = current_line.take().unwrap_or_default();
+                        for character in word.chars() {
+                            let mut proposed = line.clone();
+                            proposed.push(character);
+                            if !line.is_empty()
+                                && text_width(&proposed, Self::FONT_SIZE, false)
+                                    > Self::MAX_LINE_WIDTH
+                            {
+                                lines.push(line);
+                                line = character.to_string();
+                            } else {
+                                line = proposed;
+                            }
+                        }
+                        proposed_line = line;
+                    } else {
+                        if let Some(line) = current_line.take() {
+                            lines.push(line);
+                        }
+                        proposed_line = word.to_string();
+                    }
+                }
+
+                current_line = Some(proposed_line);
+            }
+
+            if required {
+                lines.push(current_line.take().unwrap_or_default());
+            }
+        }
+
+        if let Some(line) = current_line {
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    /// The width and height of the area inside the border.
+    fn padded_size(&self) -> (f32, f32) {
+        (
+            self.text_width.max(Self::MIN_WIDTH) + Self::PADDING * 2.0,
+            Self::LINE_HEIGHT * self.lines.len() as f32 + Self::PADDING * 2.0,
+        )
+    }
+
+    /// The size of the whole bubble, including the border and the tail.
+    fn size(&self) -> (f32, f32) {
+        let (width, height) = self.padded_size();
+        (
+            width + Self::STROKE_WIDTH,
+            height + Self::STROKE_WIDTH + Self::TAIL_HEIGHT,
+        )
+    }
+
+    /// Work out where the top left corner of the bubble goes for a sprite
+    /// with the given bounds, moving the bubble to the other side of the
+    /// sprite if it only fits there.  This follows Scratch's
+    /// `_positionBubble`.
+    fn position(&mut self, bounds: Rectangle) -> (f32, f32) {
+        let (width, height) = self.size();
+        let stage_left = -f32::from(SCRATCH_WIDTH) / 2.0;
+        let stage_right = f32::from(SCRATCH_WIDTH) / 2.0;
+        let stage_top = f32::from(SCRATCH_HEIGHT) / 2.0;
+
+        if self.on_sprite_right
+            && bounds.right + width > stage_right
+            && bounds.left - width > stage_left
+        {
+            self.on_sprite_right = false;
+            self.costume = None;
+        } else if !self.on_sprite_right
+            && bounds.left - width < stage_left
+            && bounds.right + width < stage_right
+        {
+            self.on_sprite_right = true;
+            self.costume = None;
+        }
+
+        let x = if self.on_sprite_right {
+            bounds.right.min(stage_right - width).max(stage_left)
+        } else {
+            (bounds.left - width)
+                .max(stage_left)
+                .min(stage_right - width)
+        };
+        let y = (bounds.top + height).min(stage_top);
+
+        (x, y)
+    }
+
+    /// Get the rendered bubble, rendering it if needed.
+    fn costume(&mut self, window: &SDL2Facade) -> Option<&Costume> {
+        if self.costume.is_none() {
+            self.costume = Costume::from_svg_str(window, String::from("bubble"), &self.svg()).ok();
+        }
+
+        self.costume.as_ref()
+    }
+
+    /// The bubble as svg source.
+    fn svg(&self) -> String {
+        let (width, height) = self.size();
+        let (w, h) = self.padded_size();
+        let r = Self::CORNER_RADIUS;
+
+        // The rounded box, ending at the start of the tail.
+        let mut path = format!(
+            "M{r},{h} A{r},{r} 0 0 1 0,{} L0,{r} A{r},{r} 0 0 1 {r},0 L{},0 A{r},{r} 0 0 1 {w},{r} L{w},{} A{r},{r} 0 0 1 {},{h}",
+            h - r,
+            w - r,
+            h - r,
+            w - r,
+        );
+
+        // The tail, relative to the bottom right corner.
+        let (tx, ty) = (w - r, h);
+        match self.kind {
+            BubbleKind::Say => path.push_str(&format!(
+                " C{},{} {},{} {},{} A2,2 0 0 1 {},{} C{},{} {},{} {},{} Z",
+                tx, ty + 4.0, tx + 4.0, ty + 8.0, tx + 4.0, ty + 10.0,
+                tx + 2.0, ty + 12.0,
+                tx - 1.0, ty + 12.0, tx - 11.0, ty + 8.0, tx - 16.0, ty,
+            )),
+            BubbleKind::Think => path.push_str(&format!(
+                " L{},{ty} A4,4 0 0 1 {},{ty} Z M{},{} A2.25,2.25 0 1 1 {},{} A2.25,2.25 0 1 1 {},{} M{tx},{} A1.5,1.5 0 1 1 {},{} A1.5,1.5 0 1 1 {tx},{}",
+                tx - 12.0, tx - 20.0,
+                tx - 7.0, ty + 7.25, tx - 11.5, ty + 7.25, tx - 7.0, ty + 7.25,
+                ty + 9.5, tx - 3.0, ty + 9.5, ty + 9.5,
+            )),
+        }
+
+        // The bubble is drawn with its tail on the right, and flipped when it
+        // is to the right of the sprite so the tail points back at it.
+        let flip = if self.on_sprite_right {
+            format!(r#" transform="translate({w},0) scale(-1,1)""#)
+        } else {
+            String::new()
+        };
+
+        let text: String = self
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(line_number, line)| {
+                format!(
+                    r#"<text x="{}" y="{}">{}</text>"#,
+                    Self::PADDING,
+                    Self::PADDING
+                        + Self::LINE_HEIGHT * line_number as f32
+                        + Self::FONT_HEIGHT_RATIO * Self::FONT_SIZE,
+                    escape_xml(line)
+                )
+            })
+            .collect();
+
+        // The fill is drawn over the inner half of the stroke.
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}"><g transform="translate({half},{half})"><g{flip}><path d="{path}" fill="none" stroke="#000000" stroke-opacity="0.15" stroke-width="{stroke}"/><path d="{path}" fill="#ffffff"/></g><g font-family="Helvetica" font-size="{font_size}" fill="#575e75" xml:space="preserve">{text}</g></g></svg>"##,
+            half = Self::STROKE_WIDTH / 2.0,
+            stroke = Self::STROKE_WIDTH,
+            font_size = Self::FONT_SIZE,
+        )
+    }
+}
+
+/// The width of a line of text in the font used for bubbles and monitors.
+fn text_width(text: &str, font_size: f32, bold: bool) -> f32 {
+    use resvg::usvg::{NodeExt, Tree};
+
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="1" height="1"><text font-family="Helvetica" font-size="{font_size}" font-weight="{}" xml:space="preserve">{}</text></svg>"#,
+        if bold { "bold" } else { "normal" },
+        escape_xml(text)
+    );
+
+    SVG_OPTIONS
+        .with(|options| Tree::from_str(&svg, &options.to_ref()))
+        .ok()
+        .and_then(|tree| tree.root.calculate_bbox())
+        .map_or(0.0, |bbox| bbox.right() as f32)
+}
+
+/// Escape text so that it can be put in svg source.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// How a monitor shows its variable or list.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MonitorMode {
+    /// The variable name and its value.
+    Default,
+    /// Only the value, in a larger box.
+    Large,
+    /// The variable name and its value, with a slider below.
+    Slider,
+    /// A scrollable list with item numbers.
+    List,
+}
+
+/// A monitor showing the value of a variable or the items of a list over the
+/// stage.
+#[derive(Debug)]
+pub struct Monitor {
+    /// The id of the variable or list.
+    id: String,
+    /// The name of the variable or list.
+    name: String,
+    /// The sprite the variable or list belongs to, or `None` if it is global.
+    sprite_name: Option<String>,
+    mode: MonitorMode,
+    /// The top left corner, in pixels from the top left of the stage.
+    x: f32,
+    y: f32,
+    /// The size of list monitors.  Zero means the default size.
+    width: f32,
+    height: f32,
+    visible: bool,
+    slider_min: f32,
+    slider_max: f32,
+    /// Whether the slider only sets whole numbers.
+    is_discrete: bool,
+    /// The first list item shown.
+    scroll: usize,
+    /// What the monitor showed when it was last rendered, and the rendered
+    /// monitor.
+    rendered: Option<(Vec<String>, usize, Costume)>,
+}
+
+impl Monitor {
+    const FONT_SIZE: f32 = 12.0;
+    const LARGE_FONT_SIZE: f32 = 15.0;
+    const PADDING: f32 = 5.0;
+    const HEIGHT: f32 = 26.0;
+    const SLIDER_HEIGHT: f32 = 18.0;
+    const MIN_SLIDER_WIDTH: f32 = 120.0;
+    const MIN_VALUE_WIDTH: f32 = 40.0;
+    const DEFAULT_LIST_WIDTH: f32 = 100.0;
+    const DEFAULT_LIST_HEIGHT: f32 = 200.0;
+    /// The height of the list header and footer.
+    const LIST_BAR_HEIGHT: f32 = 20.0;
+    const LIST_ROW_HEIGHT: f32 = 22.0;
+
+    pub fn new(id: String, name: String, mode: MonitorMode) -> Self {
+        Self {
+            id,
+            name,
+            sprite_name: None,
+            mode,
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            visible: true,
+            slider_min: 0.0,
+            slider_max: 100.0,
+            is_discrete: true,
+            scroll: 0,
+            rendered: None,
+        }
+    }
+
+    /// Show a variable or list that belongs to a sprite.
+    pub fn sprite(mut self, sprite_name: String) -> Self {
+        self.sprite_name = Some(sprite_name);
+        self
+    }
+
+    pub fn position(mut self, x: f32, y: f32) -> Self {
+        self.x = x;
+        self.y = y;
+        self
+    }
+
+    pub fn size(mut self, width: f32, height: f32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn slider(mut self, min: f32, max: f32, is_discrete: bool) -> Self {
+        self.slider_min = min;
+        self.slider_max = max;
+        self.is_discrete = is_discrete;
+        self
+    }
+
+    pub fn visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    fn is_list(&self) -> bool {
+        self.mode == MonitorMode::List
+    }
+
+    /// The name shown on the monitor.  Sprite variables are prefixed with the
+    /// name of the sprite, like in Scratch.
+    fn label(&self) -> String {
+        match &self.sprite_name {
+            Some(sprite_name) => format!("{sprite_name}: {}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// The text shown for the current value of the variable, or for each item
+    /// of the list.
+    fn contents(&self, stage: &Stage) -> Vec<String> {
+        let sprite = self
+            .sprite_name
+            .as_ref()
+            .and_then(|name| stage.get_sprite(name.clone()));
+        let sprite = sprite.as_ref().map(|sprite| sprite.lock().unwrap());
+
+        if self.is_list() {
+            let list = match &sprite {
+                Some(sprite) => sprite.lists.get(&self.id),
+                None => stage.lists.get(&self.id),
+            };
+            list.map_or_else(Vec::new, |(_, items)| items.iter().map(String).collect())
+        } else {
+            let variable = match &sprite {
+                Some(sprite) => sprite.variables.get(&self.id),
+                None => stage.variables.get(&self.id),
+            };
+            // Like Scratch, numbers are shown with at most six decimal places.
+            let text = match variable {
+                Some((_, Value::Num(n))) => String(&Value::Num((*n * 1e6).round() / 1e6)),
+                Some((_, value)) => String(value),
+                None => String::new(),
+            };
+            vec![text]
+        }
+    }
+
+    /// The size of a list monitor.
+    fn list_size(&self) -> (f32, f32) {
+        (
+            if self.width > 0.0 {
+                self.width
+            } else {
+                Self::DEFAULT_LIST_WIDTH
+            },
+            if self.height > 0.0 {
+                self.height
+            } else {
+                Self::DEFAULT_LIST_HEIGHT
+            },
+        )
+    }
+
+    /// How many items a list monitor has room for.
+    fn list_rows(&self) -> usize {
+        let (_, height) = self.list_size();
+        (((height - Self::LIST_BAR_HEIGHT * 2.0) / Self::LIST_ROW_HEIGHT).floor() as usize).max(1)
+    }
+
+    /// The size of the monitor as it was last drawn, in stage pixels.
+    fn rendered_size(&self) -> (f32, f32) {
+        self.rendered
+            .as_ref()
+            .map_or((0.0, 0.0), |(_, _, costume)| costume.size())
+    }
+
+    /// Whether a point in stage pixels from the top left of the stage is over
+    /// the monitor.
+    fn contains(&self, (x, y): (f32, f32)) -> bool {
+        let (width, height) = self.rendered_size();
+        self.visible && x >= self.x && x <= self.x + width && y >= self.y && y <= self.y + height
+    }
+
+    /// Get the rendered monitor, rendering it again if what it shows has
+    /// changed.
+    fn costume(&mut self, window: &SDL2Facade, contents: Vec<String>) -> Option<&Costume> {
+        if self.is_list() {
+            self.scroll = self
+                .scroll
+                .min(contents.len().saturating_sub(self.list_rows()));
+        }
+
+        let up_to_date = matches!(
+            &self.rendered,
+            Some((rendered_contents, scroll, _)) if *rendered_contents == contents && *scroll == self.scroll
+        );
+
+        if !up_to_date {
+            let svg = match self.mode {
+                MonitorMode::List => self.list_svg(&contents),
+                _ => self.variable_svg(&contents[0]),
+            };
+            self.rendered = Costume::from_svg_str(window, self.label(), &svg)
+                .ok()
+                .map(|costume| (contents, self.scroll, costume));
+        }
+
+        self.rendered.as_ref().map(|(_, _, costume)| costume)
+    }
+
+    /// The svg for a variable monitor.
+    fn variable_svg(&self, value: &str) -> String {
+        if self.mode == MonitorMode::Large {
+            let width = (text_width(value, Self::LARGE_FONT_SIZE, false) + Self::PADDING * 2.0)
+                .max(Self::MIN_VALUE_WIDTH)
+                .ceil();
+            let height = Self::HEIGHT - 2.0;
+            return format!(
+                r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}"><rect x="0.5" y="0.5" width="{}" height="{}" rx="4" fill="#ff8c1a" stroke="#db6e00"/><text x="{}" y="17" text-anchor="middle" font-family="Helvetica" font-size="{}" fill="#ffffff" xml:space="preserve">{}</text></svg>"##,
+                width - 1.0,
+                height - 1.0,
+                width / 2.0,
+                Self::LARGE_FONT_SIZE,
+                escape_xml(value),
+            );
+        }
+
+        let label = self.label();
+        let label_width = text_width(&label, Self::FONT_SIZE, true).ceil();
+        let value_width = (text_width(value, Self::FONT_SIZE, false) + Self::PADDING * 2.0)
+            .max(Self::MIN_VALUE_WIDTH)
+            .ceil();
+        let value_x = Self::PADDING * 2.0 + label_width;
+
+        let (mut width, mut height) = (value_x + value_width + Self::PADDING, Self::HEIGHT);
+        let mut slider = String::new();
+        if self.mode == MonitorMode::Slider {
+            width = width.max(Self::MIN_SLIDER_WIDTH);
+            height += Self::SLIDER_HEIGHT;
+
+            let (start, end) = self.slider_track(width);
+            let handle = start + (end - start) * self.slider_fraction(value);
+            let y = Self::HEIGHT + Self::SLIDER_HEIGHT / 2.0 - 2.0;
+            slider = format!(
+                r##"<line x1="{start}" y1="{y}" x2="{end}" y2="{y}" stroke="#c4ccd9" stroke-width="4" stroke-linecap="round"/><circle cx="{handle}" cy="{y}" r="6" fill="#ffffff" stroke="#ff8c1a" stroke-width="2"/>"##
+            );
+        }
+
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}"><rect x="0.5" y="0.5" width="{}" height="{}" rx="4" fill="#e6f0ff" stroke="#c4ccd9"/><g font-family="Helvetica" font-size="{font_size}" xml:space="preserve"><text x="{}" y="17" font-weight="bold" fill="#575e75">{}</text><rect x="{value_x}" y="4" width="{value_width}" height="18" rx="4" fill="#ff8c1a"/><text x="{}" y="17" text-anchor="middle" fill="#ffffff">{}</text></g>{slider}</svg>"##,
+            width - 1.0,
+            height - 1.0,
+            Self::PADDING,
+            escape_xml(&label),
+            value_x + value_width / 2.0,
+            escape_xml(value),
+            font_size = Self::FONT_SIZE,
+        )
+    }
+
+    /// The left and right ends of the slider track, for a monitor of the
+    /// given width.
+    fn slider_track(&self, width: f32) -> (f32, f32) {
+        (Self::PADDING * 2.0, width - Self::PADDING * 2.0)
+    }
+
+    /// Whether a point in stage pixels is over the slider of a slider
+    /// monitor.
+    fn slider_contains(&self, position: (f32, f32)) -> bool {
+        self.mode == MonitorMode::Slider
+            && self.contains(position)
+            && position.1 >= self.y + Self::HEIGHT
+    }
+
+    /// The value for a point on the slider, `x` stage pixels from the left
+    /// of the stage.  Like Scratch, discrete sliders only give whole numbers,
+    /// and others go in steps of 0.01.
+    fn slider_value(&self, x: f32) -> f32 {
+        let (start, end) = self.slider_track(self.rendered_size().0);
+        let fraction = ((x - self.x - start) / (end - start)).clamp(0.0, 1.0);
+        let value = self.slider_min + fraction * (self.slider_max - self.slider_min);
+
+        if self.is_discrete {
+            value.round()
+        } else {
+            (value * 100.0).round() / 100.0
+        }
+    }
+
+    /// Where a value is between the ends of the slider, from 0 to 1.
+    fn slider_fraction(&self, value: &str) -> f32 {
+        let value = toNumber(&Value::from(value.to_string())) as f32;
+        let fraction = (value - self.slider_min) / (self.slider_max - self.slider_min);
+        if fraction.is_finite() {
+            fraction.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// The svg for a list monitor.
+    fn list_svg(&self, items: &[String]) -> String {
+        let (width, height) = self.list_size();
+        let bar = Self::LIST_BAR_HEIGHT;
+        let visible = &items[self.scroll..items.len().min(self.scroll + self.list_rows())];
+
+        let index_width = text_width(
+            &(self.scroll + visible.len()).to_string(),
+            Self::FONT_SIZE,
+            true,
+        )
+        .ceil()
+            + Self::PADDING;
+        let item_x = index_width + Self::PADDING;
+        let item_width = width - item_x - Self::PADDING;
+
+        let mut rows = String::new();
+        for (row, item) in visible.iter().enumerate() {
+            let y = bar + 1.0 + Self::LIST_ROW_HEIGHT * row as f32;
+            rows.push_str(&format!(
+                r##"<clipPath id="row{row}"><rect x="{item_x}" y="{y}" width="{item_width}" height="20"/></clipPath><text x="{}" y="{}" font-weight="bold" fill="#575e75">{}</text><rect x="{item_x}" y="{y}" width="{item_width}" height="20" rx="3" fill="#fc662c" stroke="#e64d00"/><text x="{}" y="{}" fill="#ffffff" clip-path="url(#row{row})">{}</text>"##,
+                Self::PADDING,
+                y + 14.0,
+                self.scroll + row + 1,
+                item_x + 4.0,
+                y + 14.0,
+                escape_xml(item),
+            ));
+        }
+        if items.is_empty() {
+            rows = format!(
+                r##"<text x="{}" y="{}" text-anchor="middle" fill="#575e75">(empty)</text>"##,
+                width / 2.0,
+                height / 2.0,
+            );
+        }
+
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}"><rect x="0.5" y="0.5" width="{}" height="{}" rx="4" fill="#e6f0ff" stroke="#c4ccd9"/><g font-family="Helvetica" font-size="{font_size}" xml:space="preserve"><rect x="0.5" y="0.5" width="{}" height="{bar}" rx="4" fill="#ffffff" stroke="#c4ccd9"/><text x="{}" y="14" text-anchor="middle" font-weight="bold" fill="#575e75">{}</text>{rows}<rect x="0.5" y="{}" width="{}" height="{bar}" rx="4" fill="#ffffff" stroke="#c4ccd9"/><text x="{}" y="{}" text-anchor="middle" fill="#575e75">length {}</text></g></svg>"##,
+            width - 1.0,
+            height - 1.0,
+            width - 1.0,
+            width / 2.0,
+            escape_xml(&self.label()),
+            height - bar - 0.5,
+            width - 1.0,
+            width / 2.0,
+            height - 6.0,
+            items.len(),
+            font_size = Self::FONT_SIZE,
+        )
+    }
+}
+
+/// A question asked with the ask and wait block.
+struct Question {
+    id: Uuid,
+    text: String,
+    /// The sprite that asked, or `None` if the stage asked.
+    sprite: Option<Rc<Mutex<Sprite>>>,
+    /// Whether the question is shown in the sprite's speech bubble instead of
+    /// in the ask box.  This is decided when the question comes up.
+    in_bubble: bool,
+}
+
+impl Question {
+    /// Remove the question from the speech bubble of the sprite that asked.
+    fn remove_bubble(&self) {
+        if let (true, Some(sprite)) = (self.in_bubble, &self.sprite) {
+            sprite.lock().unwrap().bubble = None;
+        }
+    }
+}
+
+/// The box at the bottom of the stage that questions are answered in, and the
+/// questions waiting to be answered.
+#[derive(Default)]
+struct AskPrompt {
+    /// The questions waiting to be answered.  The first one is being asked.
+    questions: VecDeque<Question>,
+    /// What has been typed in the ask box so far.
+    input: String,
+    /// What the ask box showed when it was last rendered, and the rendered
+    /// ask box.
+    rendered: Option<(String, String, Costume)>,
+}
+
+impl AskPrompt {
+    /// The space between the ask box and the edge of the stage.
+    const MARGIN: f32 = 8.0;
+    /// The ask box spans the 480 pixel wide stage.
+    const WIDTH: f32 = 480.0 - Self::MARGIN * 2.0;
+    const INPUT_HEIGHT: f32 = 32.0;
+    const BUTTON_RADIUS: f32 = 13.0;
+    const FONT_SIZE: f32 = 14.0;
+
+    /// Whether a question is being asked.
+    fn is_active(&self) -> bool {
+        !self.questions.is_empty()
+    }
+
+    /// Whether a question has not been answered yet.
+    fn is_waiting(&self, id: Uuid) -> bool {
+        self.questions.iter().any(|question| question.id == id)
+    }
+
+    /// Show the first question in the queue.  A sprite that is visible asks
+    /// in a speech bubble.
+    fn ask_next_question(&mut self) {
+        let Some(question) = self.questions.front_mut() else {
+            return;
+        };
+        let Some(sprite) = &question.sprite else {
+            return;
+        };
+
+        let mut sprite = sprite.lock().unwrap();
+        question.in_bubble = sprite.visible;
+        if question.in_bubble {
+            sprite.show_bubble(BubbleKind::Say, Value::from(question.text.clone()));
+        }
+    }
+
+    /// Finish the current question and ask the next one.
+    fn answer_question(&mut self) {
+        if let Some(question) = self.questions.pop_front() {
+            question.remove_bubble();
+        }
+
+        self.ask_next_question();
+    }
+
+    /// Drop the questions asked by a sprite, or by the stage if `sprite` is
+    /// `None`, like Scratch does when the sprite is deleted or its scripts
+    /// are stopped.  If one of them was being asked, the next question is
+    /// asked instead.
+    ///
+    /// The sprite must not be locked.
+    fn clear_questions_of(&mut self, sprite: Option<&Rc<Mutex<Sprite>>>) {
+        let asked_by = |question: &Question| match (&question.sprite, sprite) {
+            (Some(asker), Some(sprite)) => Rc::ptr_eq(asker, sprite),
+            (None, None) => true,
+            _ => false,
+        };
+
+        let was_asking = self.questions.front().is_some_and(asked_by);
+        if was_asking {
+            if let Some(question) = self.questions.pop_front() {
+                question.remove_bubble();
+            }
+        }
+
+        self.questions.retain(|question| !asked_by(question));
+        if was_asking {
+            self.ask_next_question();
+        }
+    }
+
+    /// The question shown in the ask box.  This is empty when a sprite asks
+    /// in a speech bubble.
+    fn question(&self) -> &str {
+        match self.questions.front() {
+            Some(Question {
+                text,
+                in_bubble: false,
+                ..
+            }) => text,
+            _ => "",
+        }
+    }
+
+    /// The top of the text field, from the top of the ask box.
+    fn input_top(&self) -> f32 {
+        if self.question().is_empty() {
+            Self::MARGIN
+        } else {
+            Self::MARGIN + 22.0
+        }
+    }
+
+    /// The height of the ask box.
+    fn height(&self) -> f32 {
+        self.input_top() + Self::INPUT_HEIGHT + Self::MARGIN
+    }
+
+    /// Whether a point in stage pixels is over the button that submits the
+    /// answer.
+    fn is_over_button(&self, (x, y): (f32, f32)) -> bool {
+        let top = f32::from(SCRATCH_HEIGHT) - Self::MARGIN - self.height();
+        let center_x = Self::MARGIN + Self::WIDTH - Self::MARGIN - Self::INPUT_HEIGHT / 2.0;
+        let center_y = top + self.input_top() + Self::INPUT_HEIGHT / 2.0;
+
+        (x - center_x).hypot(y - center_y) <= Self::BUTTON_RADIUS
+    }
+
+    /// Get the rendered ask box, rendering it again if it has changed.  This
+    /// is `None` when no question is being asked.
+    fn costume(&mut self, window: &SDL2Facade) -> Option<&Costume> {
+        if !self.is_active() {
+            self.rendered = None;
+            return None;
+        }
+
+        let up_to_date = matches!(
+            &self.rendered,
+            Some((question, input, _)) if question == self.question() && *input == self.input
+        );
+
+        if !up_to_date {
+            self.rendered = Costume::from_svg_str(window, String::from("ask box"), &self.svg())
+                .ok()
+                .map(|costume| (self.question().to_string(), self.input.clone(), costume));
+        }
+
+        self.rendered.as_ref().map(|(_, _, costume)| costume)
+    }
+
+    /// The ask box as svg source.
+    fn svg(&self) -> String {
+        let (width, height) = (Self::WIDTH, self.height());
+        let top = self.input_top();
+        let input_width = width - Self::MARGIN * 2.0;
+        let button_x = Self::MARGIN + input_width - Self::INPUT_HEIGHT / 2.0;
+        let button_y = top + Self::INPUT_HEIGHT / 2.0;
+
+        let question = if self.question().is_empty() {
+            String::new()
+        } else {
+            format!(
+                r##"<text x="{}" y="{}" font-weight="bold" fill="#575e75">{}</text>"##,
+                Self::MARGIN + 4.0,
+                Self::MARGIN + 14.0,
+                escape_xml(self.question())
+            )
+        };
+
+        // Keep the end of long answers in view.
+        let text_x = Self::MARGIN + 12.0;
+        let text_space = input_width - Self::INPUT_HEIGHT - 16.0;
+        let typed_width = text_width(&self.input, Self::FONT_SIZE, false);
+        let shift = (typed_width - text_space).max(0.0);
+
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}"><rect x="1" y="1" width="{}" height="{}" rx="8" fill="#ffffff" stroke="#d9d9d9" stroke-width="2"/><g font-family="Helvetica" font-size="{font_size}" xml:space="preserve">{question}<rect x="{}" y="{top}" width="{input_width}" height="{input_height}" rx="{}" fill="#ffffff" stroke="#d9d9d9" stroke-width="2"/><clipPath id="input"><rect x="{text_x}" y="{top}" width="{text_space}" height="{input_height}"/></clipPath><g clip-path="url(#input)"><text x="{}" y="{}" fill="#575e75">{}</text><line x1="{}" y1="{}" x2="{}" y2="{}" stroke="#575e75"/></g></g><circle cx="{button_x}" cy="{button_y}" r="{}" fill="#855cd6"/><path d="M{},{} l4,4 l7,-8" fill="none" stroke="#ffffff" stroke-width="2.5" stroke-linecap="round" stroke-linejoin="round"/></svg>"##,
+            width - 2.0,
+            height - 2.0,
+            Self::MARGIN,
+            Self::INPUT_HEIGHT / 2.0,
+            text_x - shift,
+            button_y + 5.0,
+            escape_xml(&self.input),
+            text_x - shift + typed_width + 1.0,
+            button_y - 8.0,
+            text_x - shift + typed_width + 1.0,
+            button_y + 8.0,
+            Self::BUTTON_RADIUS,
+            button_x - 5.5,
+            button_y,
+            font_size = Self::FONT_SIZE,
+            input_height = Self::INPUT_HEIGHT,
+        )
+    }
+}
+
+/// Convert scratch coordinates to pixels from the top left of the stage, which
+/// monitors are positioned in.
+fn scratch_to_stage_pixels((x, y): (f32, f32)) -> (f32, f32) {
+    (
+        x + f32::from(SCRATCH_WIDTH) / 2.0,
+        f32::from(SCRATCH_HEIGHT) / 2.0 - y,
+    )
+}
+
+/// The main project class.  This is in charge of running threads and
+/// redrawing the screen.
+pub struct Program<'a> {
+    threads: Vec<Thread>,
+    //objects: Vec<Rc<Mutex<Sprite>>>,
+    window: &'a SDL2Facade,
+    /// Every costume in the project.  Sprites, their clones and the stage
+    /// hold references to these, so each costume is only loaded once.
+    costumes: Vec<Rc<Costume>>,
+    /// The shaders costumes are drawn with.
+    shaders: ShaderCache,
+    /// The clock that waits and timers are measured against.
+    clock: Rc<Mutex<RuntimeClock>>,
+    /// Whether threads are currently held.
+    paused: bool,
+    /// Whether a single frame should be run while paused.
+    step_requested: bool,
+    /// Input received while paused, applied once the project runs again.
+    buffered_events: VecDeque<Event>,
+    /// The green flag and stop buttons, if they are shown.
+    controls: Option<Controls>,
+    /// The index of the slider monitor being dragged, if any.
+    dragged_slider: Option<usize>,
+    /// The draggable sprite the mouse was pressed on, if any.
+    dragged_sprite: Option<SpriteDrag>,
+}
+
+/// A draggable sprite picked up by the mouse.
+struct SpriteDrag {
+    sprite: Rc<Mutex<Sprite>>,
+    /// Where the sprite is relative to the mouse.
+    offset: (f32, f32),
+    /// Where the mouse was pressed, in scratch coordinates.
+    start: (f32, f32),
+    /// Whether the mouse has moved far enough for this to be a drag instead
+    /// of a click.
+    dragged: bool,
+}
+
+impl SpriteDrag {
+    /// How far the mouse has to move, in scratch units, before a press
+    /// becomes a drag.
+    const THRESHOLD: f32 = 3.0;
+}
+
+impl<'a> Program<'a> {
+    /// Run 1 tick
+    ///
+    /// While paused this does nothing, unless `step_frame` was called, in which
+    /// case exactly one frame is run.
+    fn tick(&mut self, stage: Rc<Mutex<Stage>>) {
+        if self.paused {
+            if !self.step_requested {
+                return;
+            }
+            self.step_requested = false;
+            self.clock.lock().unwrap().advance(FRAME_DURATION);
+        }
+
+        // Input that arrived while paused is applied before running scripts.
+        while let Some(event) = self.buffered_events.pop_front() {
+            self.apply_event(event, stage.clone());
+        }
+
+        self.add_threads_from_stage(stage.clone());
+
+
+
+
+
+        // stop all threads if stop all is called
+
+        self.stop_if_needed(stage.clone());
+
+
+        // for (i, thread) in &mut self.threads.iter_mut().enumerate() {
+        //     let returned = match thread.obj_index {
+        //         Some(obj_index_unwrapped) => thread.function.resume_with(Target::new(
+        //             self.objects[obj_index_unwrapped].clone(),
+        //             stage.clone(),
+        //         )),
+
+        //         None => thread
+        //             .function
+        //             .resume_with(Target::new_stage(stage.clone())),
+        //     };
+
+        //     match returned {
+        //         GeneratorState::Yielded(yielded_sprite) => {
+        //             if let Some(zz) = yielded_sprite {
+        //                 match zz.sprite {
+        //                     Some(y) => {
+        //                         if let Some(obj_index_unwrapped) = thread.obj_index {
+        //                             self.objects[obj_index_unwrapped] = y;
+        //                             *stage = zz.stage;
+        //                         }
+        //                     }
+        //                     None => *stage = zz.stage,
+        //                 }
+        //             }
+        //         }
+        //         GeneratorState::Complete(x) => thread.complete = true,
+        //     }
+        // }
+
+        // // remove all threads that are complete.
+        // self.threads.retain(|x| !x.complete);
+        for thread in &mut self.threads {
+            // if the thread has not started yet, go to the next one.
+            if !thread.running {
+                continue;
+            }
+            let waker = dummy_waker();
+            let mut context = Context::from_waker(&waker);
+            match thread.poll(&mut context) {
+                Poll::Pending => { /*The task is not done, so do nothing.*/ }
+                Poll::Ready(()) => {
+                    /*The task is done, so rewind it for the next time its hat fires.*/
+                    thread.stop();
+                }
+            }
+        }
+        // Apply stops requested during this tick before the next frame.
+        self.stop_if_needed(stage.clone());
+        self.delete_sprites_if_needed(stage);
+    }
+
+
+    /// Stop any scripts that need to be stopped because a stop block has been
+    /// run.
+    fn stop_if_needed(&mut self, stage: Rc<Mutex<Stage>>){
+        // Possible scenarios:
+        // 1 `stop all` on stage or sprite
+        // 2 `stop this script` on sprite or stage - the script returns itself
+        // 3 `stop other scripts in sprite` on sprite
+        // 4 `stop other scripts in stage` on stage
+        //
+        // Requests are reset once handled so they only apply once.
+
+        let stop_all = std::mem::replace(&mut stage.lock().unwrap().stop_all, StopType::None);
+
+        // Scenario 1: `stop all`
+        if stop_all == StopType::All {
+            self.stop_all(stage);
+            return;
+        }
+
+        // helper function to stop a thread
+        fn stop_thread(x: &mut Thread) {
+            x.stop();
+        }
+
+        // Scenario 4: `stop other scripts in stage` on stage
+        let mut stage = stage.lock().unwrap();
+        if let StopType::OtherScriptsInSprite { thread_uuid } = stop_all {
+            self.threads
+                .iter_mut()
+                .filter(|x| x.sprite_uuid.is_none())
+                .filter(|x| x.thread_uuid != thread_uuid)
+                .for_each(stop_thread);
+            stage.prompt.clear_questions_of(None);
+        }
+
+        // Scenario 3: `stop other scripts in sprite` on sprite
+        let mut stopped_sprites = Vec::new();
+        for sprite_rc in &stage.sprites {
+            let mut sprite = sprite_rc.lock().unwrap();
+
+            if let StopType::OtherScriptsInSprite { thread_uuid } = sprite.possible_stop{
+                self.threads.iter_mut()
+                    .filter(|x| x.sprite_uuid == Some(sprite.uuid))
+                    .filter(|x| x.thread_uuid != thread_uuid)
+                    .for_each(stop_thread);
+                stopped_sprites.push(sprite_rc.clone());
+            }
+            sprite.possible_stop = StopType::None;
+        }
+
+        // The stopped scripts will never get their answers.
+        for sprite in &stopped_sprites {
+            stage.prompt.clear_questions_of(Some(sprite));
+        }
+    }
+
+    /// Stop everything, like the stop sign or a `stop all` block: every
+    /// thread is stopped, clones are deleted and graphic effects are cleared
+    /// on the stage and all sprites.
+    ///
+    /// Sounds would be halted here too, but sound playback is not
+    /// implemented yet.
+    fn stop_all(&mut self, stage: Rc<Mutex<Stage>>) {
+        for thread in &mut self.threads {
+            thread.stop();
+        }
+
+        let mut stage = stage.lock().unwrap();
+        stage.stop_all = StopType::None;
+        stage.threads_to_add.clear();
+        stage.effects.clear();
+        stage.prompt = AskPrompt::default();
+        for sprite in &stage.sprites {
+            let mut sprite = sprite.lock().unwrap();
+            sprite.effects.clear();
+            sprite.bubble = None;
+            sprite.possible_stop = StopType::None;
+        }
+        stage.delete_clones();
+    }
+
+    fn new(window: &'a SDL2Facade) -> Self {
+        Program {
+            threads: Vec::new(),
+            window,
+            costumes: Vec::new(),
+            shaders: ShaderCache::new(),
+            clock: Rc::new(Mutex::new(RuntimeClock::new())),
+            paused: false,
+            step_requested: false,
+            buffered_events: VecDeque::new(),
+            controls: None,
+            dragged_slider: None,
+            dragged_sprite: None,
+        }
+    }
+
+    /// Hold all threads and freeze the runtime clock.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.clock.lock().unwrap().pause();
+    }
+
+    /// Continue running after a pause.
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.step_requested = false;
+        self.clock.lock().unwrap().resume();
+    }
+
+    /// Whether the project is currently paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Run exactly one frame on the next tick while paused.  The runtime clock
+    /// moves forward by one frame, so waits and the timer advance as well.
+    pub fn step_frame(&mut self) {
+        if self.paused {
+            self.step_requested = true;
+        }
+    }
+
+    /// Handle an input event from the window.  While paused, events are
+    /// buffered and applied when the next frame runs.
+    ///
+    /// Clicks on the controls overlay are handled straight away and are not
+    /// passed on to the project.
+    pub fn handle_event(&mut self, event: Event, stage: Rc<Mutex<Stage>>) {
+        if let (
+            Some(controls),
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            },
+        ) = (&self.controls, &event)
+        {
+            let position = Mouse::sdl2scratch((*x as f32, *y as f32), self.window);
+            match controls.button_at(position) {
+                Some(ControlButton::GreenFlag) => return self.click_flag(stage),
+                Some(ControlButton::Stop) => return self.click_stop(stage),
+                None => (),
+            }
+        }
+
+        // Sliders can be dragged even while paused.  Dragging a slider does
+        // not count as pressing the mouse on the stage.
+        match event {
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => {
+                let position = Mouse::sdl2scratch((x as f32, y as f32), self.window);
+                let slider = stage.lock().unwrap().slider_at(position);
+                if let Some(index) = slider {
+                    self.dragged_slider = Some(index);
+                    return self.drag_slider(index, position, stage);
+                }
+            }
+            Event::MouseMotion { x, y, .. } => {
+                if let Some(index) = self.dragged_slider {
+                    let position = Mouse::sdl2scratch((x as f32, y as f32), self.window);
+                    self.drag_slider(index, position, stage.clone());
+                }
+            }
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                ..
+            } if self.dragged_slider.take().is_some() => return,
+            _ => (),
+        }
+
+        if self.paused {
+            self.buffered_events.push_back(event);
+        } else {
+            self.apply_event(event, stage);
+        }
+    }
+
+    /// Set the variable of a slider monitor from the position of the mouse,
+    /// in scratch coordinates.
+    fn drag_slider(&mut self, index: usize, position: (f32, f32), stage: Rc<Mutex<Stage>>) {
+        let (variable, value, sprite) = {
+            let stage = stage.lock().unwrap();
+            let Some(monitor) = stage.monitors.get(index) else {
+                return;
+            };
+            let (x, _) = scratch_to_stage_pixels(position);
+            let sprite = monitor
+                .sprite_name
+                .as_ref()
+                .and_then(|name| stage.get_sprite(name.clone()));
+            (
+                (monitor.name.clone(), monitor.id.clone()),
+                monitor.slider_value(x),
+                sprite,
+            )
+        };
+
+        blocks::set_variable(sprite, stage, variable, Value::from(value));
+    }
+
+    /// Update the keyboard and mouse state from an input event.
+    fn apply_event(&mut self, event: Event, stage: Rc<Mutex<Stage>>) {
+        let mut s = stage.lock().unwrap();
+
+        // While a question is asked, typing goes to the ask box instead of
+        // the project.
+        if s.prompt.is_active() {
+            match event {
+                Event::TextInput { text, .. } => return s.prompt.input.push_str(&text),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => {
+                    s.prompt.input.pop();
+                    return;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return | Keycode::KpEnter),
+                    ..
+                } => {
+                    return s.answer_question();
+                }
+                Event::KeyDown { .. } => return,
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let position = Mouse::sdl2scratch((x as f32, y as f32), self.window);
+                    if s.prompt.is_over_button(scratch_to_stage_pixels(position)) {
+                        return s.answer_question();
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        match event {
+            Event::KeyDown {
+                keycode: Some(key), ..
+            } => s.keyboard.press_key(key),
+            Event::KeyUp {
+                keycode: Some(key), ..
+            } => s.keyboard.release_key(key),
+            Event::MouseMotion { x, y, .. } => {
+                s.mouse.set_sdl_position([x as f64, y as f64], self.window)
+            }
+            Event::MouseButtonDown { mouse_btn: m, .. } => s.mouse.set_button_down(m),
+            Event::MouseButtonUp { mouse_btn: m, .. } => s.mouse.set_button_up(m),
+            Event::MouseWheel { y, .. } => {
+                let position = s.mouse.scratch_position;
+                s.scroll_monitor(position, y);
+            }
+            _ => (),
+        }
+        drop(s);
+
+        match event {
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => {
+                let position = Mouse::sdl2scratch((x as f32, y as f32), self.window);
+                self.press_mouse(position, stage);
+            }
+            Event::MouseMotion { x, y, .. } => {
+                let position = Mouse::sdl2scratch((x as f32, y as f32), self.window);
+                self.drag_sprite(position, stage);
+            }
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => self.release_mouse(),
+            _ => (),
+        }
+    }
+
+    /// Press the mouse on whatever is under it, in scratch coordinates.
+    ///
+    /// A draggable sprite is picked up, and only counts as clicked if the
+    /// mouse is released without dragging it.  Anything else is clicked
+    /// straight away.
+    fn press_mouse(&mut self, position: (f32, f32), stage: Rc<Mutex<Stage>>) {
+        let sprite = stage.lock().unwrap().sprite_at(position);
+        let Some(sprite) = sprite else {
+            return self.click(None);
+        };
+
+        let (draggable, uuid, offset) = {
+            let sprite = sprite.lock().unwrap();
+            let offset = (sprite.x - position.0, sprite.y - position.1);
+            (sprite.draggable, sprite.uuid, offset)
+        };
+        if !draggable {
+            return self.click(Some(uuid));
+        }
+
+        self.dragged_sprite = Some(SpriteDrag {
+            sprite,
+            offset,
+            start: position,
+            dragged: false,
+        });
+    }
+
+    /// Move the sprite being dragged so that it stays at the same place under
+    /// the mouse.  Fencing keeps it on the stage.  The sprite goes to the
+    /// front once it starts being dragged.
+    fn drag_sprite(&mut self, position: (f32, f32), stage: Rc<Mutex<Stage>>) {
+        let Some(drag) = &mut self.dragged_sprite else {
+            return;
+        };
+
+        if !drag.dragged {
+            let distance = (position.0 - drag.start.0).hypot(position.1 - drag.start.1);
+            if distance < SpriteDrag::THRESHOLD {
+                return;
+            }
+            drag.dragged = true;
+            drag.sprite.lock().unwrap().dragging = true;
+            blocks::go_to_front_or_back(drag.sprite.clone(), stage.clone(), Value::from("front"));
+        }
+
+        let fencing = stage.lock().unwrap().fencing;
+        let (x, y) = (position.0 + drag.offset.0, position.1 + drag.offset.1);
+        drag.sprite.lock().unwrap().force_xy(x, y, fencing);
+    }
+
+    /// Drop the sprite being dragged.  If the mouse never moved far enough to
+    /// drag it, the sprite was clicked instead.
+    fn release_mouse(&mut self) {
+        if let Some(drag) = self.dragged_sprite.take() {
+            let uuid = {
+                let mut sprite = drag.sprite.lock().unwrap();
+                sprite.dragging = false;
+                sprite.uuid
+            };
+            if !drag.dragged {
+                self.click(Some(uuid));
+            }
+        }
+    }
+
+    /// Start the "when this sprite clicked" scripts of a sprite, or the "when
+    /// stage clicked" scripts if `sprite` is `None`.  Scripts that are already
+    /// running start again from the top.
+    fn click(&mut self, sprite: Option<Uuid>) {
+        for thread in &mut self.threads {
+            if thread.start == StartType::SpriteClicked && thread.sprite_uuid == sprite {
+                thread.stop();
+                thread.running = true;
+            }
+        }
+    }
+
+    /// Get a handle to the program's runtime clock.
+    ///
+    /// The stage holds on to this so that blocks can measure time against it.
+    /// Replacing the contents with `RuntimeClock::new_virtual()` lets the
+    /// project be driven by a virtual clock instead.
+    fn clock(&self) -> Rc<Mutex<RuntimeClock>> {
+        self.clock.clone()
+    }
+
+    /// Simulate the flag being clicked.  Like in Scratch, everything is
+    /// stopped first, then all threads with FlagClicked hat blocks are started
+    /// from the top.
+    pub fn click_flag(&mut self, stage: Rc<Mutex<Stage>>) {
+        self.click_stop(stage);
+
+        for thread in &mut self.threads {
+            if thread.start == StartType::FlagClicked {
+                thread.running = true;
+            }
+        }
+    }
+
+    /// Simulate the stop sign being clicked.
+    pub fn click_stop(&mut self, stage: Rc<Mutex<Stage>>) {
+        self.stop_all(stage.clone());
+        self.delete_sprites_if_needed(stage);
+    }
+
+    /// Choose whether the green flag and stop buttons are drawn over the
+    /// stage.
+    pub fn show_controls(&mut self, show: bool) {
+        self.controls = if show {
+            Some(Controls::new(self.window).expect("Built in controls should be valid svg"))
+        } else {
+            None
+        };
+    }
+
+    fn add_threads_from_stage(&mut self, stage: Rc<Mutex<Stage>>) {
+        let mut stage = stage.lock().unwrap();
+
+        for thread in stage.threads_to_add.drain(0..) {
+            self.add_thread(thread);
+        }
+    }
+
+    /// Check all sprites and see if they need to be deleted; if they do, delete
+    /// them.
+    fn delete_sprites_if_needed(&mut self, stage: Rc<Mutex<Stage>>) {
+        let mut stage = stage.lock().unwrap();
+        let mut deleted_sprites = Vec::new();
+
+        stage.sprites.retain(|sprite_rc| {
+            let sprite = sprite_rc.lock().unwrap();
+
+            if sprite.to_be_deleted {
+                self.threads
+                    .retain(|thread| thread.sprite_uuid != Some(sprite.uuid));
+                deleted_sprites.push(sprite_rc.clone());
+            }
+
+            !sprite.to_be_deleted
+        });
+
+        // Nothing is waiting for the answers to questions deleted sprites asked.
+        for sprite in &deleted_sprites {
+            stage.prompt.clear_questions_of(Some(sprite));
+        }
+    }
+
+    /// Render the stage, all sprites, and everything else that needs to be
+    /// rendered.
+    fn render(&mut self, stage: Rc<Mutex<Stage>>) {
+        let mut stage = stage.lock().unwrap();
+
+        let mut target = self.window.draw();
+
+        target.clear_color(1.0, 1.0, 1.0, 1.0); // Clear the background color to white
+
+        if let Some(backdrop) = stage.costumes.get(stage.costume) {
+            let transform = model_matrix(backdrop, (0.0, 0.0), 0.0, [1.0, 1.0]);
+            let shader = self
+                .shaders
+                .get(self.window, stage.effects.keys(), DrawMode::Default);
+            backdrop.draw(self.window, &mut target, transform, shader, &stage.effects);
+        }
+
+        for stamp in &stage.stamps {
+            let shader = self.shaders.get(self.window, [].iter(), DrawMode::Default);
+            stamp.costume.draw(
+                self.window,
+                &mut target,
+                stamp.transform,
+                shader,
+                &HashMap::new(),
+            );
+        }
+
+        for sprite in stage.sprites.clone() {
+            let mut sprite = sprite.lock().unwrap();
+
+            if !sprite.visible {
+                continue;
+            }
+
+            if let Some(transform) = sprite.model_matrix() {
+                let shader =
+                    self.shaders
+                        .get(self.window, sprite.effects.keys(), DrawMode::Default);
+                sprite.costumes[sprite.costume].draw(
+                    self.window,
+                    &mut target,
+                    transform,
+                    shader,
+                    &sprite.effects,
+                );
+            }
+        }
+
+        // Bubbles are drawn above every sprite, except for hidden sprites.
+        for sprite in stage.sprites.clone() {
+            let mut sprite = sprite.lock().unwrap();
+            if !sprite.visible {
+                continue;
+            }
+
+            let Some(bounds) = sprite.bounds() else {
+                continue;
+            };
+            let Some(bubble) = &mut sprite.bubble else {
+                continue;
+            };
+
+            let (x, y) = bubble.position(bounds);
+            let (width, height) = bubble.size();
+            if let Some(costume) = bubble.costume(self.window) {
+                let transform = model_matrix(
+                    costume,
+                    (x + width / 2.0, y - height / 2.0),
+                    0.0,
+                    [1.0, 1.0],
+                );
+                let shader = self.shaders.get(self.window, [].iter(), DrawMode::Default);
+                costume.draw(self.window, &mut target, transform, shader, &HashMap::new());
+            }
+        }
+
+        // Monitors are drawn over everything on the stage.
+        let contents: Vec<_> = stage
+            .monitors
+            .iter()
+            .map(|monitor| monitor.visible.then(|| monitor.contents(&stage)))
+            .collect();
+        for (monitor, contents) in stage.monitors.iter_mut().zip(contents) {
+            let Some(contents) = contents else {
+                continue;
+            };
+            let (x, y) = (monitor.x, monitor.y);
+            if let Some(costume) = monitor.costume(self.window, contents) {
+                let (width, height) = costume.size();
+                let center = (
+                    x + width / 2.0 - f32::from(SCRATCH_WIDTH) / 2.0,
+                    f32::from(SCRATCH_HEIGHT) / 2.0 - y - height / 2.0,
+                );
+                let transform = model_matrix(costume, center, 0.0, [1.0, 1.0]);
+                let shader = self.shaders.get(self.window, [].iter(), DrawMode::Default);
+                costume.draw(self.window, &mut target, transform, shader, &HashMap::new());
+            }
+        }
+
+        if let Some(costume) = stage.prompt.costume(self.window) {
+            let (width, height) = costume.size();
+            let center = (
+                AskPrompt::MARGIN + width / 2.0 - f32::from(SCRATCH_WIDTH) / 2.0,
+                AskPrompt::MARGIN + height / 2.0 - f32::from(SCRATCH_HEIGHT) / 2.0,
+            );
+            let transform = model_matrix(costume, center, 0.0, [1.0, 1.0]);
+            let shader = self.shaders.get(self.window, [].iter(), DrawMode::Default);
+            costume.draw(self.window, &mut target, transform, shader, &HashMap::new());
+        }
+
+        if let Some(controls) = &self.controls {
+            let shader = self.shaders.get(self.window, [].iter(), DrawMode::Default);
+            controls.draw(self.window, &mut target, shader);
+        }
+
+        target.finish().unwrap();
+    }
+
+    // /// Add threads from a sprite to the program.
+    // /// This _moves_ the threads out of the sprite.
+    // fn add_threads(&mut self, mut threads: Vec<Thread>) {
+    //     // self.threads.append(&mut sprite.blocks);
+    //     self.threads.append(&mut threads)
+    // }
+
+    /// Get a sprite by a name, or return null
+    fn get_sprite(&self, name: String) -> Option<Rc<Mutex<Sprite>>> {
+        None
+        //for sprite in self.objects.clone(){
+        //    let locked_sprite = sprite.lock().unwrap();
+        //    if locked_sprite.name == name{
+        //        return Some(sprite.clone());
+        //    }
+        //}
+    }
+
+    /// Add a costume to the program, and get a reference to it that can be
+    /// given to a sprite or the stage.
+    pub fn add_costume(&mut self, costume: Costume) -> Rc<Costume> {
+        let costume = Rc::new(costume);
+        self.costumes.push(costume.clone());
+        costume
+    }
+
+    /// Add a thread.
+    fn add_thread(&mut self, thread: Thread) {
+        self.threads.push(thread);
+    }
+
+    fn add_threads<T: Iterator<Item = Thread>>(&mut self, threads: T) {
+        for thread in threads {
+            self.add_thread(thread);
+        }
+    }
+
+    /// Create openGL vertexes for a rectangle.
+    fn rect(top_left: [f32; 2], bottom_right: [f32; 2]) -> Vec<Vertex> {
+        let top_right = [bottom_right[0], top_left[1]];
+        let bottom_left = [top_left[0], bottom_right[1]];
+
+        vec![
+            Vertex::new(bottom_left, [0.0, 0.0]),
+            Vertex::new(bottom_right, [1.0, 0.0]),
+            Vertex::new(top_right, [1.0, 1.0]),
+            Vertex::new(top_right, [1.0, 1.0]),
+            Vertex::new(top_left, [0.0, 1.0]),
+            Vertex::new(bottom_left, [0.0, 0.0]),
+        ]
+    }
+
+    fn identity_matrix() -> [[f32; 4]; 4] {
+        [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+    }
+
+    fn ortho_matrix() -> [[f32; 4]; 4] {
+        let left = -f32::from(SCRATCH_HALF_WIDTH);
+        let right = f32::from(SCRATCH_HALF_WIDTH);
+        let top = f32::from(SCRATCH_HALF_HEIGHT);
+        let bottom = -f32::from(SCRATCH_HALF_HEIGHT);
+
+        let near = -1.0;
+        let far = 1.0;
+
+        [
+            [2.0 / (right - left), 0.0, 0.0, 0.0],
+            [0.0, 2.0 / (top - bottom), 0.0, 0.0],
+            [0.0, 0.0, 2.0 / (near - far), 0.0],
+            [
+                (right + left) / (left - right),
+                (top + bottom) / (bottom - top),
+                (far + near) / (near - far),
+                1.0,
+            ],
+        ]
+    }
+
+    fn get_vertex_shader() -> &'static str {
+        r#"
+
+#ifdef DRAW_MODE_line
+    uniform vec2 u_stageSize;
+    uniform float u_lineThickness;
+    uniform float u_lineLength;
+    // The X and Y components of u_penPoints hold the first pen point. The Z and W components hold the difference between
+    // the second pen point and the first. This is done because calculating the difference in the shader leads to floating-
+    // point error when both points have large-ish coordinates.
+    uniform vec4 u_penPoints;
+
+    // Add this to divisors to prevent division by 0, which results in NaNs propagating through calculations.
+    // Smaller values can cause problems on some mobile devices.
+    const float epsilon = 1e-3;
+#endif
+
+#if !(defined(DRAW_MODE_line) || defined(DRAW_MODE_background))
+    uniform mat4 u_projectionMatrix;
+    uniform mat4 u_modelMatrix;
+    in vec2 a_texCoord;
+#endif
+
+in vec2 a_position;
+
+out vec2 v_texCoord;
+
+void main() {
+	#ifdef DRAW_MODE_line
+        // Calculate a rotated ("tight") bounding box around the two pen points.
+        // Yes, we're doing this 6 times (once per vertex), but on actual GPU hardware,
+        // it's still faster than doing it in JS combined with the cost of uniformMatrix4fv.
+
+        // Expand line bounds by sqrt(2) / 2 each side-- this ensures that all antialiased pixels
+        // fall within the quad, even at a 45-degree diagonal
+        vec2 position = a_position;
+        float expandedRadius = (u_lineThickness * 0.5) + 1.4142135623730951;
+
+        // The X coordinate increases along the length of the line. It's 0 at the center of the origin point
+        // and is in pixel-space (so at n pixels along the line, its value is n).
+        v_texCoord.x = mix(0.0, u_lineLength + (expandedRadius * 2.0), a_position.x) - expandedRadius;
+        // The Y coordinate is perpendicular to the line. It's also in pixel-space.
+        v_texCoord.y = ((a_position.y - 0.5) * expandedRadius) + 0.5;
+
+        position.x *= u_lineLength + (2.0 * expandedRadius);
+        position.y *= 2.0 * expandedRadius;
+
+        // 1. Center around first pen point
+        position -= expandedRadius;
+
+        // 2. Rotate quad to line angle
+        vec2 pointDiff = u_penPoints.zw;
+        // Ensure line has a nonzero length so it's rendered properly
+        // As long as either component is nonzero, the line length will be nonzero
+        // If the line is zero-length, give it a bit of horizontal length
+        pointDiff.x = (abs(pointDiff.x) < epsilon && abs(pointDiff.y) < epsilon) ? epsilon : pointDiff.x;
+        // The `normalized` vector holds rotational values equivalent to sine/cosine
+        // We're applying the standard rotation matrix formula to the position to rotate the quad to the line angle
+        // pointDiff can hold large values so we must divide by u_lineLength instead of calling GLSL's normalize function:
+        // https://asawicki.info/news_1596_watch_out_for_reduced_precision_normalizelength_in_opengl_es
+        vec2 normalized = pointDiff / max(u_lineLength, epsilon);
+        position = mat2(normalized.x, normalized.y, -normalized.y, normalized.x) * position;
+
+        // 3. Translate quad
+        position += u_penPoints.xy;
+
+        // 4. Apply view transform
+        position *= 2.0 / u_stageSize;
+        gl_Position = vec4(position, 0, 1);
+	#elif defined(DRAW_MODE_background)
+        gl_Position = vec4(a_position * 2.0, 0, 1);
+	#else
+        gl_Position = u_projectionMatrix * u_modelMatrix * vec4(a_position, 0, 1);
+        v_texCoord = a_texCoord;
+	#endif
+}
+"#
+    }
+
+    fn get_fragment_shader() -> &'static str {
+        r#"
+
+#ifdef DRAW_MODE_silhouette
+    uniform vec4 u_silhouetteColor;
+#else // DRAW_MODE_silhouette
+    # ifdef ENABLE_color
+        uniform float u_color;
+    # endif // ENABLE_color
+    # ifdef ENABLE_brightness
+        uniform float u_brightness;
+    # endif // ENABLE_brightness
+#endif // DRAW_MODE_silhouette
+
+#ifdef DRAW_MODE_colorMask
+    uniform vec3 u_colorMask;
+    uniform float u_colorMaskTolerance;
+#endif // DRAW_MODE_colorMask
+
+#ifdef ENABLE_fisheye
+    uniform float u_fisheye;
+#endif // ENABLE_fisheye
+#ifdef ENABLE_whirl
+    uniform float u_whirl;
+#endif // ENABLE_whirl
+#ifdef ENABLE_pixelate
+    uniform float u_pixelate;
+    uniform vec2 u_skinSize;
+#endif // ENABLE_pixelate
+#ifdef ENABLE_mosaic
+    uniform float u_mosaic;
+#endif // ENABLE_mosaic
+#ifdef ENABLE_ghost
+    uniform float u_ghost;
+#endif // ENABLE_ghost
+
+#ifdef DRAW_MODE_line
+    uniform vec4 u_lineColor;
+    uniform float u_lineThickness;
+    uniform float u_lineLength;
+#endif // DRAW_MODE_line
+
+#ifdef DRAW_MODE_background
+    uniform vec4 u_backgroundColor;
+#endif // DRAW_MODE_background
+
+uniform sampler2D u_skin;
+
+#ifndef DRAW_MODE_background
+    in vec2 v_texCoord;
+#endif
+
+// Add this to divisors to prevent division by 0, which results in NaNs propagating through calculations.
+// Smaller values can cause problems on some mobile devices.
+const float epsilon = 1e-3;
+
+#if !defined(DRAW_MODE_silhouette) && (defined(ENABLE_color))
+    // Branchless color conversions based on code from:
+    // http://www.chilliant.com/rgb2hsv.html by Ian Taylor
+    // Based in part on work by Sam Hocevar and Emil Persson
+    // See also: https://en.wikipedia.org/wiki/HSL_and_HSV#Formal_derivation
+
+
+    // Convert an RGB color to Hue, Saturation, and Value.
+    // All components of input and output are expected to be in the [0,1] range.
+    vec3 convertRGB2HSV(vec3 rgb) {
+        // Hue calculation has 3 cases, depending on which RGB component is largest, and one of those cases involves a "mod"
+        // operation. In order to avoid that "mod" we split the M==R case in two: one for G<B and one for B>G. The B>G case
+        // will be calculated in the negative and fed through abs() in the hue calculation at the end.
+        // See also: https://en.wikipedia.org/wiki/HSL_and_HSV#Hue_and_chroma
+        const vec4 hueOffsets = vec4(0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0);
+
+        // temp1.xy = sort B & G (largest first)
+        // temp1.z = the hue offset we'll use if it turns out that R is the largest component (M==R)
+        // temp1.w = the hue offset we'll use if it turns out that R is not the largest component (M==G or M==B)
+        vec4 temp1 = rgb.b > rgb.g ? vec4(rgb.bg, hueOffsets.wz) : vec4(rgb.gb, hueOffsets.xy);
+
+        // temp2.x = the largest component of RGB ("M" / "Max")
+        // temp2.yw = the smaller components of RGB, ordered for the hue calculation (not necessarily sorted by magnitude!)
+        // temp2.z = the hue offset we'll use in the hue calculation
+        vec4 temp2 = rgb.r > temp1.x ? vec4(rgb.r, temp1.yzx) : vec4(temp1.xyw, rgb.r);
+
+        // m = the smallest component of RGB ("min")
+        float m = min(temp2.y, temp2.w);
+
+        // Chroma = M - m
+        float C = temp2.x - m;
+
+        // Value = M
+        float V = temp2.x;
+
+        return vec3(
+            abs(temp2.z + (temp2.w - temp2.y) / (6.0 * C + epsilon)), // Hue
+            C / (temp2.x + epsilon), // Saturation
+            V); // Value
+    }
+
+    vec3 convertHue2RGB(float hue) {
+        float r = abs(hue * 6.0 - 3.0) - 1.0;
+        float g = 2.0 - abs(hue * 6.0 - 2.0);
+        float b = 2.0 - abs(hue * 6.0 - 4.0);
+        return clamp(vec3(r, g, b), 0.0, 1.0);
+    }
+
+    vec3 convertHSV2RGB(vec3 hsv) {
+        vec3 rgb = convertHue2RGB(hsv.x);
+        float c = hsv.z * hsv.y;
+        return rgb * c + hsv.z - c;
+    }
+#endif // !defined(DRAW_MODE_silhouette) && (defined(ENABLE_color))
+
+const vec2 kCenter = vec2(0.5, 0.5);
+
+void main() {
+	#if !(defined(DRAW_MODE_line) || defined(DRAW_MODE_background))
+        vec2 texcoord0 = v_texCoord;
+
+        #ifdef ENABLE_mosaic
+            texcoord0 = fract(u_mosaic * texcoord0);
+        #endif // ENABLE_mosaic
+
+        #ifdef ENABLE_pixelate
+            {
+                // TODO: clean up "pixel" edges
+                vec2 pixelTexelSize = u_skinSize / u_pixelate;
+                texcoord0 = (floor(texcoord0 * pixelTexelSize) + kCenter) / pixelTexelSize;
+            }
+        #endif // ENABLE_pixelate
+
+        #ifdef ENABLE_whirl
+            {
+                const float kRadius = 0.5;
+                vec2 offset = texcoord0 - kCenter;
+                float offsetMagnitude = length(offset);
+                float whirlFactor = max(1.0 - (offsetMagnitude / kRadius), 0.0);
+                float whirlActual = u_whirl * whirlFactor * whirlFactor;
+                float sinWhirl = sin(whirlActual);
+                float cosWhirl = cos(whirlActual);
+                mat2 rotationMatrix = mat2(
+                    cosWhirl, -sinWhirl,
+                    sinWhirl, cosWhirl
+                );
+
+                texcoord0 = rotationMatrix * offset + kCenter;
+            }
+        #endif // ENABLE_whirl
+
+        #ifdef ENABLE_fisheye
+            {
+                vec2 vec = (texcoord0 - kCenter) / kCenter;
+                float vecLength = length(vec);
+                float r = pow(min(vecLength, 1.0), u_fisheye) * max(1.0, vecLength);
+                vec2 unit = vec / vecLength;
+
+                texcoord0 = kCenter + r * unit * kCenter;
+            }
+        #endif // ENABLE_fisheye
+
+        gl_FragColor = texture2D(u_skin, texcoord0);
+
+        #if defined(ENABLE_color) || defined(ENABLE_brightness)
+            // Divide premultiplied alpha values for proper color processing
+            // Add epsilon to avoid dividing by 0 for fully transparent pixels
+            gl_FragColor.rgb = clamp(gl_FragColor.rgb / (gl_FragColor.a + epsilon), 0.0, 1.0);
+
+            #ifdef ENABLE_color
+                {
+                    vec3 hsv = convertRGB2HSV(gl_FragColor.xyz);
+
+                    // this code forces grayscale values to be slightly saturated
+                    // so that some slight change of hue will be visible
+                    const float minLightness = 0.11 / 2.0;
+                    const float minSaturation = 0.09;
+                    if (hsv.z < minLightness) hsv = vec3(0.0, 1.0, minLightness);
+                    else if (hsv.y < minSaturation) hsv = vec3(0.0, minSaturation, hsv.z);
+
+                    hsv.x = mod(hsv.x + u_color, 1.0);
+                    if (hsv.x < 0.0) hsv.x += 1.0;
+
+                    gl_FragColor.rgb = convertHSV2RGB(hsv);
+                }
+            #endif // ENABLE_color
+
+            #ifdef ENABLE_brightness
+                gl_FragColor.rgb = clamp(gl_FragColor.rgb + vec3(u_brightness), vec3(0), vec3(1));
+            #endif // ENABLE_brightness
+
+            // Re-multiply color values
+            gl_FragColor.rgb *= gl_FragColor.a + epsilon;
+
+        #endif // defined(ENABLE_color) || defined(ENABLE_brightness)
+
+        #ifdef ENABLE_ghost
+            gl_FragColor *= u_ghost;
+        #endif // ENABLE_ghost
+
+        #ifdef DRAW_MODE_silhouette
+            // Discard fully transparent pixels for stencil test
+            if (gl_FragColor.a == 0.0) {
+                discard;
+            }
+            // switch to u_silhouetteColor only AFTER the alpha test
+            gl_FragColor = u_silhouetteColor;
+        #else // DRAW_MODE_silhouette
+
+            #ifdef DRAW_MODE_colorMask
+                vec3 maskDistance = abs(gl_FragColor.rgb - u_colorMask);
+                vec3 colorMaskTolerance = vec3(u_colorMaskTolerance, u_colorMaskTolerance, u_colorMaskTolerance);
+                if (any(greaterThan(maskDistance, colorMaskTolerance)))
+                {
+                    discard;
+                }
+            #endif // DRAW_MODE_colorMask
+        #endif // DRAW_MODE_silhouette
+
+        #ifdef DRAW_MODE_straightAlpha
+            // Un-premultiply alpha.
+            gl_FragColor.rgb /= gl_FragColor.a + epsilon;
+        #endif
+
+	#endif // !(defined(DRAW_MODE_line) || defined(DRAW_MODE_background))
+
+	#ifdef DRAW_MODE_line
+        // Maaaaagic antialiased-line-with-round-caps shader.
+
+        // "along-the-lineness". This increases parallel to the line.
+        // It goes from negative before the start point, to 0.5 through the start to the end, then ramps up again
+        // past the end point.
+        float d = ((v_texCoord.x - clamp(v_texCoord.x, 0.0, u_lineLength)) * 0.5) + 0.5;
+
+        // Distance from (0.5, 0.5) to (d, the perpendicular coordinate). When we're in the middle of the line,
+        // d will be 0.5, so the distance will be 0 at points close to the line and will grow at points further from it.
+        // For the "caps", d will ramp down/up, giving us rounding.
+        // See https://www.youtube.com/watch?v=PMltMdi1Wzg for a rough outline of the technique used to round the lines.
+        float line = distance(vec2(0.5), vec2(d, v_texCoord.y)) * 2.0;
+        // Expand out the line by its thickness.
+        line -= ((u_lineThickness - 1.0) * 0.5);
+        // Because "distance to the center of the line" decreases the closer we get to the line, but we want more opacity
+        // the closer we are to the line, invert it.
+        gl_FragColor = u_lineColor * clamp(1.0 - line, 0.0, 1.0);
+	#endif // DRAW_MODE_line
+
+	#ifdef DRAW_MODE_background
+        gl_FragColor = u_backgroundColor;
+	#endif
+}
+"#
+    }
+}
+
+/// Load a bitmap image (png, jpg, gif, etc.) into a texture.
+fn get_texture_from_image(
+    window: &SDL2Facade,
+    image: &image::RgbaImage,
+) -> Result<glium::texture::Texture2d, &'static str> {
+    let image =
+        glium::texture::RawImage2d::from_raw_rgba_reversed(image.as_raw(), image.dimensions());
+
+    glium::texture::Texture2d::new(window, image).or(Err("Cannot create texture"))
+}
+
+/// The fonts Scratch uses for text in costumes, and the files in `FONT_DIRECTORY`
+/// they are loaded from.
+pub const SCRATCH_FONTS: [(&str, &str); 7] = [
+    ("Sans Serif", "NotoSans-Medium.ttf"),
+    ("Serif", "SourceSerifPro-Regular.otf"),
+    ("Handwriting", "handlee-regular.ttf"),
+    ("Marker", "Knewave.ttf"),
+    ("Curly", "Griffy-Regular.ttf"),
+    ("Pixel", "Grand9K-Pixel.ttf"),
+    ("Scratch", "Scratch.ttf"),
+];
+
+/// Fonts from Scratch 2 projects, and the Scratch font they are shown with.
+const SCRATCH_2_FONTS: [(&str, &str); 4] = [
+    ("Helvetica", "Sans Serif"),
+    ("Donegal", "Serif"),
+    ("Gloria", "Handwriting"),
+    ("Mystery", "Curly"),
+];
+
+/// The directory the fonts are stored in, next to the assets.
+pub const FONT_DIRECTORY: &str = "fonts";
+
+/// The file the player reads its settings from, next to the assets.
+pub const CONFIG_FILE: &str = "player.cfg";
+
+/// The environment variable the username can be set with.
+pub const USERNAME_VARIABLE: &str = "SCRATCH_USERNAME";
+
+/// Find the username reported by the username block.  The `--username`
+/// command line flag comes first, then the `SCRATCH_USERNAME` environment
+/// variable, then a `username = ...` line in `CONFIG_FILE`.  Like offline
+/// Scratch, the username is empty otherwise.
+pub fn configured_username() -> String {
+    username_from_args(std::env::args().skip(1))
+        .or_else(|| std::env::var(USERNAME_VARIABLE).ok())
+        .or_else(|| {
+            fs::read_to_string(CONFIG_FILE)
+                .ok()
+                .and_then(|config| config_value(&config, "username"))
+        })
+        .unwrap_or_default()
+}
+
+/// Find the username in `--username NAME` or `--username=NAME` command line
+/// arguments.
+fn username_from_args(mut args: impl Iterator<Item = String>) -> Option<String> {
+    while let Some(arg) = args.next() {
+        if arg == "--username" {
+            return args.next();
+        }
+        if let Some(username) = arg.strip_prefix("--username=") {
+            return Some(username.to_string());
+        }
+    }
+    None
+}
+
+/// Find a setting in a config file of `key = value` lines.  Blank lines and
+/// lines starting with `#` are skipped.
+fn config_value(config: &str, key: &str) -> Option<String> {
+    config
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .find(|(name, _)| name.trim() == key)
+        .map(|(_, value)| value.trim().to_string())
+}
+
+thread_local! {
+    /// The options used to parse every svg.  These hold the font database,
+    /// so it is only loaded once.
+    static SVG_OPTIONS: resvg::usvg::Options = svg_options();
+}
+
+/// Create the svg options, loading the Scratch fonts into the font database
+/// under the names Scratch uses for them.
+fn svg_options() -> resvg::usvg::Options {
+    use resvg::usvg::{fontdb::Database, Options};
+
+    let mut fontdb = Database::new();
+    for (family, file) in SCRATCH_FONTS {
+        let path = PathBuf::from(FONT_DIRECTORY).join(file);
+        let mut font = Database::new();
+        if let Err(error) = font.load_font_file(&path) {
+            eprintln!(
+                "Could not load font `{family}` from {}: {error}",
+                path.display()
+            );
+            continue;
+        }
+
+        let names = std::iter::once(family).chain(
+            SCRATCH_2_FONTS
+                .iter()
+                .filter(|(_, scratch_3_family)| *scratch_3_family == family)
+                .map(|(scratch_2_family, _)| *scratch_2_family),
+        );
+        for name in names {
+            for face in font.faces() {
+                let mut face = face.clone();
+                face.family = name.to_string();
+                fontdb.push_face_info(face);
+            }
+        }
+    }
+    fontdb.set_sans_serif_family("Sans Serif");
+    fontdb.set_serif_family("Serif");
+    fontdb.set_cursive_family("Handwriting");
+    fontdb.set_fantasy_family("Curly");
+
+    Options {
+        font_family: String::from("Sans Serif"),
+        fontdb,
+        ..Options::default()
+    }
+}
+
+/// Rasterize svg source into a texture.
+fn get_texture_from_svg(
+    window: &SDL2Facade,
+    svg: &str,
+    scale: f32,
+) -> Result<glium::texture::Texture2d, &'static str> {
+    get_texture_from_image(window, &rasterize_svg(svg, scale)?)
+}
+
+/// Rasterize svg source into an image.
+fn rasterize_svg(svg: &str, scale: f32) -> Result<image::RgbaImage, &'static str> {
+    use resvg::tiny_skia::{Pixmap, Transform};
+    use resvg::usvg::{FitTo, Tree};
+
+    let tree = SVG_OPTIONS
+        .with(|options| Tree::from_str(svg, &options.to_ref()))
+        .or(Err("Not a readable svg file"))?;
+
+    let fit_to = FitTo::Zoom(scale);
+    let transform = Transform::default();
+    let size = fit_to.fit_to(tree.size.to_screen_size()).unwrap();
+    let mut pixmap = Pixmap::new(size.width(), size.height()).ok_or("Could not create pixmap")?;
+    let pixmapmut = pixmap.as_mut();
+
+    resvg::render(&tree, fit_to, transform, pixmapmut);
+
+    let image =
+        image::load_from_memory_with_format(&pixmap.encode_png().unwrap(), image::ImageFormat::Png)
+            .or(Err("Cannot load rendered svg file."))?
+            .to_rgba8();
+
+    Ok(image)
+}
+
+pub struct StageBuilder {
+    tempo: i32,
+    video_state: VideoState,
+    video_transparency: i32,
+    text_to_speech_language: String,
+    variables: HashMap<String, (String, Value)>,
+    lists: HashMap<String, (String, Vec<Value>)>,
+    costume: usize,
+    costumes: Vec<Rc<Costume>>,
+    sounds: Vec<Sound>,
+    volume: f32,
+    clock: Rc<Mutex<RuntimeClock>>,
+    fencing: bool,
+    monitors: Vec<Monitor>,
+    username: String,
+}
+
+impl StageBuilder {
+    pub fn new() -> Self {
+        Self {
+            tempo: 60, //BPM
+            video_state: VideoState::Off,
+            video_transparency: 0,
+            text_to_speech_language: "en".to_string(),
+            variables: HashMap::new(),
+            lists: HashMap::new(),
+            costume: 0,
+            costumes: Vec::new(),
+            sounds: Vec::new(),
+            volume: 100.0,
+            clock: Rc::new(Mutex::new(RuntimeClock::new())),
+            fencing: true,
+            monitors: Vec::new(),
+            username: String::new(),
+        }
+    }
+    pub fn build(self) -> Stage {
+        Stage {
+            tempo: self.tempo,
+            video_state: self.video_state,
+            video_transparency: self.video_transparency,
+            text_to_speech_language: self.text_to_speech_language,
+            variables: self.variables,
+            lists: self.lists,
+            costume: self.costume,
+            costumes: self.costumes,
+            sounds: self.sounds,
+            volume: self.volume,
+            sprites: Vec::new(),
+            keyboard: Keyboard::new(),
+            mouse: Mouse::new(),
+            stamps: Vec::new(),
+            answer: Value::from(String::new()),
+            prompt: AskPrompt::default(),
+            threads_to_add: VecDeque::new(),
+            effects: HashMap::new(),
+            stop_all: StopType::None,
+            clock: self.clock,
+            timer_start: Duration::ZERO,
+            fencing: self.fencing,
+            monitors: self.monitors,
+            username: self.username,
+        }
+    }
+    pub fn tempo(mut self, tempo: i32) -> Self {
+        self.tempo = tempo;
+        self
+    }
+    pub fn video_state(mut self, video_state: VideoState) -> Self {
+        self.video_state = video_state;
+        self
+    }
+    pub fn video_transparency(mut self, transparency: i32) -> Self {
+        self.video_transparency = transparency;
+        self
+    }
+    pub fn add_variable(mut self, id: String, value: (String, Value)) -> Self {
+        self.variables.insert(id, value);
+        self
+    }
+    pub fn add_list(mut self, id: String, value: (String, Vec<Value>)) -> Self {
+        self.lists.insert(id, value);
+        self
+    }
+    pub fn add_costume(mut self, costume: Rc<Costume>) -> Self {
+        self.costumes.push(costume);
+        self
+    }
+    pub fn add_sound(mut self, sound: Sound) -> Self {
+        self.sounds.push(sound);
+        self
+    }
+    pub fn set_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+    pub fn costume(mut self, costume: usize) -> Self {
+        self.costume = costume;
+        self
+    }
+    pub fn text_to_speech_language(mut self, ttsl: String) -> Self {
+        self.text_to_speech_language = ttsl;
+        self
+    }
+    /// Use the program's runtime clock instead of a separate one.
+    pub fn clock(mut self, clock: Rc<Mutex<RuntimeClock>>) -> Self {
+        self.clock = clock;
+        self
+    }
+    /// Choose whether sprites are kept on the stage when they move.  This is
+    /// on by default, as in Scratch.
+    pub fn fencing(mut self, fencing: bool) -> Self {
+        self.fencing = fencing;
+        self
+    }
+    pub fn add_monitor(mut self, monitor: Monitor) -> Self {
+        self.monitors.push(monitor);
+        self
+    }
+    /// Set the username reported by the username block.  Defaults to empty.
+    pub fn username(mut self, username: String) -> Self {
+        self.username = username;
+        self
+    }
+}
+
+/// This is the stage object.
+pub struct Stage {
+    /// The tempo, in BPM.
+    tempo: i32,
+    /// Determines if video is on or off.
+    video_state: VideoState,
+    /// The video transparency  Defaults to 50.  This has no effect if `videoState`
+    /// is off or the project does not use an extension with video input.
+    video_transparency: i32,
+    /// The text to speech language.  Defaults to the editor language.
+    text_to_speech_language: String,
+    variables: HashMap<String, (String, Value)>,
+    lists: HashMap<String, (String, Vec<Value>)>,
+    /// The current costume.  An index to the stage costumes list.
+    costume: usize,
+    /// The costumes in the stage.  These are shared with the program.
+    costumes: Vec<Rc<Costume>>,
+    /// A list of sounds owned by the stage.
+    sounds: Vec<Sound>,
+    /// The volume for the stage.
+    volume: f32,
+    keyboard: Keyboard,
+    mouse: Mouse,
+    /// A list of references to sprites.
+    sprites: Vec<Rc<Mutex<Sprite>>>,
+
+    stamps: Vec<Stamp>,
+    /// The current value of answer.
+    answer: Value,
+    /// The ask box and the questions waiting for an answer.
+    prompt: AskPrompt,
+
+    threads_to_add: VecDeque<Thread>,
+
+    effects: HashMap<Effect, f32>,
+
+    stop_all: StopType,
+    /// A handle to the program's runtime clock.
+    clock: Rc<Mutex<RuntimeClock>>,
+    /// The clock time when the timer was last reset.
+    timer_start: Duration,
+    /// Whether sprites are kept on the stage when they move.
+    fencing: bool,
+    /// The variable and list monitors, in the order they are drawn.
+    monitors: Vec<Monitor>,
+    /// The username reported by the username block.
+    username: String,
+}
+
+impl Stage {
+    fn set_answer(&mut self, v: Value) {
+        self.answer = v;
+    }
+
+    fn get_answer(&self) -> Value {
+        self.answer.clone()
+    }
+
+    fn set_costume(&mut self, mut index: f32) {
+        // round the index to a whole number
+        index = index.round();
+
+        if index.is_infinite() || index.is_nan() {
+            index = 0.0;
+        }
+
+        if self.costumes.is_empty() {
+            return;
+        }
+
+        // wrap the index around, like scratch, so it is always valid
+        index = wrap_clamp(index, 0.0, self.costumes.len() as f32 - 1.0);
+
+        self.costume = index as usize;
+    }
+
+    fn add_sprite(&mut self, sprite: Rc<Mutex<Sprite>>) {
+        self.sprites.push(sprite);
+    }
+
+    /// Get an original (non-clone) sprite by a name, or return null
+    fn get_sprite(&self, name: String) -> Option<Rc<Mutex<Sprite>>> {
+        self.sprites.clone().into_iter().find(|sprite| {
+            let sprite = sprite.lock().unwrap();
+            sprite.name == name && !sprite.is_clone()
+        })
+    }
+
+    /// Scroll the list monitor under a point, in scratch coordinates, by a
+    /// number of items.  Positive amounts scroll up.
+    fn scroll_monitor(&mut self, position: (f32, f32), amount: i32) {
+        let position = scratch_to_stage_pixels(position);
+
+        if let Some(monitor) = self
+            .monitors
+            .iter_mut()
+            .rev()
+            .find(|monitor| monitor.is_list() && monitor.contains(position))
+        {
+            // Scrolling past the end is undone when the monitor is drawn.
+            monitor.scroll = monitor.scroll.saturating_add_signed(-amount as isize);
+        }
+    }
+
+    /// Everything drawn below a sprite that overlaps some bounds, from the top
+    /// down, with how opaque each one is.  This is the other sprites, then
+    /// the pen layer of stamps, then the backdrop.
+    fn layers_below(
+        &self,
+        sprite: &Rc<Mutex<Sprite>>,
+        bounds: &Rectangle,
+    ) -> Vec<(CollisionShape, f32)> {
+        let sprites = self
+            .sprites
+            .iter()
+            .rev()
+            .filter(|other| !Rc::ptr_eq(other, sprite))
+            .filter_map(|other| {
+                let other = other.lock().unwrap();
+                other
+                    .collision_shape()
+                    .map(|shape| (shape, opacity(&other.effects)))
+            });
+
+        let stamps = self.stamps.iter().rev().map(|stamp| {
+            let shape = CollisionShape {
+                costume: stamp.costume.clone(),
+                matrix: stamp.transform,
+                bounds: Rectangle::of_matrix(&stamp.transform),
+            };
+            (shape, 1.0)
+        });
+
+        let backdrop = self.costumes.get(self.costume).map(|backdrop| {
+            let matrix = model_matrix(backdrop, (0.0, 0.0), 0.0, [1.0, 1.0]);
+            let shape = CollisionShape {
+                costume: backdrop.clone(),
+                matrix,
+                bounds: Rectangle::of_matrix(&matrix),
+            };
+            (shape, opacity(&self.effects))
+        });
+
+        sprites
+            .chain(stamps)
+            .chain(backdrop)
+            .filter(|(shape, _)| shape.bounds.intersection(bounds).is_some())
+            .collect()
+    }
+
+    /// Answer the current question with what has been typed in the ask box,
+    /// then ask the next question.
+    fn answer_question(&mut self) {
+        let answer = std::mem::take(&mut self.prompt.input);
+        self.set_answer(Value::from(answer));
+        self.prompt.answer_question();
+    }
+
+    /// Find the slider monitor with its slider under a point, in scratch
+    /// coordinates.
+    fn slider_at(&self, position: (f32, f32)) -> Option<usize> {
+        let position = scratch_to_stage_pixels(position);
+
+        self.monitors
+            .iter()
+            .rposition(|monitor| monitor.slider_contains(position))
+    }
+
+    /// Find the front-most visible sprite with a pixel under a point, in
+    /// scratch coordinates.
+    fn sprite_at(&self, point: (f32, f32)) -> Option<Rc<Mutex<Sprite>>> {
+        self.sprites
+            .iter()
+            .rev()
+            .find(|sprite| {
+                let sprite = sprite.lock().unwrap();
+                !sprite.to_be_deleted
+                    && sprite
+                        .collision_shape()
+                        .is_some_and(|shape| shape.contains(point))
+            })
+            .cloned()
+    }
+
+    /// Count the clones that currently exist.
+    fn clone_count(&self) -> usize {
+        self.sprites
+            .iter()
+            .filter(|sprite| {
+                let sprite = sprite.lock().unwrap();
+                sprite.is_clone() && !sprite.to_be_deleted
+            })
+            .count()
+    }
+
+    /// Mark every clone for deletion.
+    fn delete_clones(&mut self) {
+        for sprite in &self.sprites {
+            let mut sprite = sprite.lock().unwrap();
+            if sprite.is_clone() {
+                sprite.to_be_deleted = true;
+            }
+        }
+    }
+
+    fn add_threads<T: Iterator<Item = Thread>>(&mut self, threads: T) {
+        for thread in threads {
+            self.threads_to_add.push_back(thread);
+        }
+    }
+}
+
+/// The type of thread starter, such as flagClick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartType {
+    FlagClicked,
+    KeyPressed,
+    SpriteClicked,
+    BackdropSwitches,
+    LoudnessGreater,
+    ReceiveMessage,
+    StartAsClone(String),
+    CustomBlock,
+    NoStart,
+}
+
+impl Display for StartType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                StartType::FlagClicked => "StartType::FlagClicked".to_string(),
+                StartType::KeyPressed => "StartType::KeyPressed".to_string(),
+                StartType::SpriteClicked => "StartType::SpriteClicked".to_string(),
+                StartType::BackdropSwitches => "StartType::BackdropSwitches".to_string(),
+                StartType::LoudnessGreater => "StartType::LoudnessGreater".to_string(),
+                StartType::ReceiveMessage => "StartType::ReceiveMessage".to_string(),
+                StartType::StartAsClone(sprite) =>
+                    format!("StartType::StartAsClone(String::from(\"{sprite}\"))"),
+                StartType::CustomBlock => "StartType::CustomBlock".to_string(),
+                StartType::NoStart => "StartType::NoStart".to_string(),
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitType {
+    /// A specific time.
+    Time(u32),
+    CustomBlock,
+    None,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keyboard {
+    keys_pressed: HashSet<Keycode>,
+    key_name: HashMap<&'static str, &'static str>,
+}
+
+impl Keyboard {
+    fn new() -> Self {
+        Keyboard {
+            keys_pressed: HashSet::new(),
+            key_name: HashMap::from([
+                ("space", "SPACE"),
+                ("left arrow", "LEFT"),
+                ("up arrow", "UP"),
+                ("right arrow", "RIGHT"),
+                ("down arrow", "DOWN"),
+                ("enter", "ENTER"),
+            ]),
+        }
+    }
+
+    fn press_key(&mut self, key: Keycode) {
+        self.keys_pressed.insert(key);
+    }
+
+    fn release_key(&mut self, key: Keycode) {
+        self.keys_pressed.remove(&key);
+    }
+
+    fn key_arg_to_scratch_key(&self, arg: Value) -> String {
+        if let Value::Num(x) = arg {
+            if (48.0..=90.0).contains(&x) {
+                return String::from_utf16(&[x as u16]).expect("Proper range.");
+            }
+            return match x as u16 {
+                32 => self.key_name.get("space").unwrap().to_string(),
+                37 => self.key_name.get("left arrow").unwrap().to_string(),
+                38 => self.key_name.get("up arrow").unwrap().to_string(),
+                39 => self.key_name.get("right arrow").unwrap().to_string(),
+                40 => self.key_name.get("down arrow").unwrap().to_string(),
+                _ => unreachable!("Unknown key"),
+            };
+        }
+
+        // match arg {
+        //     Value::Num(_) => todo!(),
+        //     Value::String(_) => todo!(),
+        //     Value::Bool(_) => todo!(),
+        //     Value::Null => todo!(),
+        // }
+        let mut keyArg: String = arg.into();
+
+        if let Some(a) = self.key_name.get(&*keyArg) {
+            return a.to_string();
+        }
+
+        if keyArg.len() > 1 {
+            keyArg = keyArg.chars().next().unwrap().into();
+        }
+
+        if keyArg == *" " {
+            return self.key_name.get("SPACE").unwrap().to_string();
+        }
+
+        keyArg.to_uppercase()
+    }
+
+    fn scratch_key_to_Key(&self, arg: String) -> Option<Keycode> {
+        Some(match &*arg {
+            "A" => Keycode::A,
+            "B" => Keycode::B,
+            "C" => Keycode::C,
+            "D" => Keycode::D,
+            "E" => Keycode::E,
+            "F" => Keycode::F,
+            "G" => Keycode::G,
+            "H" => Keycode::H,
+            "I" => Keycode::I,
+            "J" => Keycode::J,
+            "K" => Keycode::K,
+            "L" => Keycode::L,
+            "M" => Keycode::M,
+            "N" => Keycode::N,
+            "O" => Keycode::O,
+            "P" => Keycode::P,
+            "Q" => Keycode::Q,
+            "R" => Keycode::R,
+            "S" => Keycode::S,
+            "T" => Keycode::T,
+            "U" => Keycode::U,
+            "V" => Keycode::V,
+            "W" => Keycode::W,
+            "X" => Keycode::X,
+            "Y" => Keycode::Y,
+            "Z" => Keycode::Z,
+            "space" => Keycode::Space,
+            "left arrow" => Keycode::Left,
+            "right arrow" => Keycode::Right,
+            "up arrow" => Keycode::Up,
+            "down arrow" => Keycode::Down,
+            "SPACE" => Keycode::Space,
+            "LEFT" => Keycode::Left,
+            "RIGHT" => Keycode::Right,
+            "UP" => Keycode::Up,
+            "DOWN" => Keycode::Down,
+            "0" => Keycode::Num0,
+            "1" => Keycode::Num1,
+            "2" => Keycode::Num2,
+            "3" => Keycode::Num3,
+            "4" => Keycode::Num4,
+            "5" => Keycode::Num5,
+            "6" => Keycode::Num6,
+            "7" => Keycode::Num7,
+            "8" => Keycode::Num8,
+            "9" => Keycode::Num9,
+            "!" => Keycode::Exclaim,
+            "@" => Keycode::At,
+            "#" => Keycode::Hash,
+            "$" => Keycode::Dollar,
+            "%" => Keycode::Percent,
+            "^" => Keycode::Caret,
+            "&" => Keycode::Ampersand,
+            "*" => Keycode::Asterisk,
+            "(" => Keycode::LeftParen,
+            ")" => Keycode::RightParen,
+            "`" => Keycode::Backquote,
+            "-" => Keycode::Minus,
+            "+" => Keycode::Plus,
+            "_" => Keycode::Underscore,
+            "=" => Keycode::Equals,
+            "[" => Keycode::LeftBracket,
+            "]" => Keycode::RightBracket,
+            "\\" => Keycode::Backslash,
+            ";" => Keycode::Semicolon,
+            ":" => Keycode::Colon,
+            "'" => Keycode::Quote,
+            "\"" => Keycode::Quotedbl,
+            "/" => Keycode::Slash,
+            "?" => Keycode::Question,
+            "." => Keycode::Period,
+            "," => Keycode::Comma,
+            _ => return None,
+        })
+    }
+
+    fn get_key_down(&self, arg: Value) -> bool {
+        if let Value::String(x) = &arg {
+            if x == &String::from("any") {
+                return !self.keys_pressed.is_empty();
+            }
+        }
+
+        if let Some(scratchKey) = self.scratch_key_to_Key(self.key_arg_to_scratch_key(arg)) {
+            self.keys_pressed.contains(&scratchKey)
+        } else {
+            false
+        }
+    }
+}
+
+/// The mouse struct.
+///
+/// This holds the position of the mouse, both in scratch coordinates and sdl coordinates.
+/// It also holds the keys that are pressed or not.
+pub struct Mouse {
+    scratch_position: (f32, f32),
+    sdl_position: (f32, f32),
+    buttons: Vec<MouseButton>,
+}
+
+impl Mouse {
+    fn new() -> Self {
+        Self {
+            scratch_position: (0.0, 0.0),
+            sdl_position: (0.0, 0.0),
+            buttons: Vec::new(),
+        }
+    }
+
+    /// Set the position of the mouse, in sdl coordinates.
+    /// This also sets the scratch position to the correct coordinates.
+    pub fn set_sdl_position(&mut self, pos: [f64; 2], window: &SDL2Facade) {
+        self.sdl_position = (pos[0] as f32, pos[1] as f32);
+        self.scratch_position = Mouse::sdl2scratch((pos[0] as f32, pos[1] as f32), window);
+    }
+
+    pub fn set_button_down(&mut self, button: MouseButton) {
+        if self.buttons.iter().find(|x| **x == button).is_none() {
+            self.buttons.push(button);
+        }
+    }
+
+    pub fn set_button_up(&mut self, button: MouseButton) {
+        if let Some((i, x)) = self.buttons.iter().enumerate().find(|x| *x.1 == button) {
+            self.buttons.remove(i);
+        }
+    }
+
+    pub fn mouse_down(&self) -> bool {
+        self.buttons.contains(&MouseButton::Left)
+            || self.buttons.contains(&MouseButton::Right)
+            || self.buttons.contains(&MouseButton::Middle)
+    }
+
+    /// Convert piston coordinates to scratch coordinates
+    fn sdl2scratch(coords: (f32, f32), window: &SDL2Facade) -> (f32, f32) {
+        let (mut x, mut y) = coords;
+
+        let (width, height) = window.window().size();
+
+        // find the ratio of scratch size to actual size
+        let ratio_x: f32 = Into::<f32>::into(SCRATCH_WIDTH) / width as f32;
+        let ratio_y: f32 = Into::<f32>::into(SCRATCH_HEIGHT) / height as f32;
+
+        // resize the coordinates to the correct size
+        x *= ratio_x;
+        y *= ratio_y;
+
+        // convert coordinates from origin at top-left to origin at center.
+        x -= Into::<f32>::into(SCRATCH_HALF_WIDTH);
+        y = -(y - (Into::<f32>::into(SCRATCH_HALF_HEIGHT)));
+
+        (x, y)
+    }
+
+    fn x(&self) -> Value {
+        Value::from(self.scratch_position.0)
+    }
+    fn y(&self) -> Value {
+        Value::from(self.scratch_position.1)
+    }
+}
+
+impl Display for Mouse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "({},{})",
+            self.scratch_position.0, self.scratch_position.1
+        )
+    }
+}
+
+/// A stamp of a sprite.
+struct Stamp {
+    /// Where the costume was on the stage when it was stamped.
+    transform: [[f32; 4]; 4],
+    costume: Rc<Costume>,
+}
+
+
+/// A simple enum to encode the different way to stop scripts
+#[derive(Debug,Copy,Clone, Eq, PartialEq)]
+enum StopType{
+    None,
+    All,
+    ThisScript,
+    OtherScriptsInSprite{thread_uuid: Uuid}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Make a stage that measures time against `clock`.
+    fn stage_with_clock(clock: &Rc<Mutex<RuntimeClock>>) -> Rc<Mutex<Stage>> {
+        Rc::new(Mutex::new(StageBuilder::new().clock(clock.clone()).build()))
+    }
+
+    /// Poll a future once, like the program does every frame.
+    fn poll_once<F: Future + ?Sized>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+        let waker = dummy_waker();
+        future.as_mut().poll(&mut Context::from_waker(&waker))
+    }
+
+    #[test]
+    fn fractional_wait() {
+        let clock = Rc::new(Mutex::new(RuntimeClock::new_virtual()));
+        let mut wait = Box::pin(blocks::wait(stage_with_clock(&clock), Value::from(0.5)));
+
+        assert!(poll_once(&mut wait).is_pending());
+        clock.lock().unwrap().advance(Duration::from_millis(499));
+        assert!(poll_once(&mut wait).is_pending());
+        clock.lock().unwrap().advance(Duration::from_millis(1));
+        assert!(poll_once(&mut wait).is_ready());
+    }
+
+    #[test]
+    fn negative_and_nan_waits_yield_once() {
+        for duration in [Value::from(-1.0), Value::from(f32::NAN)] {
+            let clock = Rc::new(Mutex::new(RuntimeClock::new_virtual()));
+            let mut wait = Box::pin(blocks::wait(stage_with_clock(&clock), duration));
+
+            assert!(poll_once(&mut wait).is_pending());
+            assert!(poll_once(&mut wait).is_ready());
+        }
+    }
+
+    #[test]
+    fn wait_does_not_finish_while_paused() {
+        let clock = Rc::new(Mutex::new(RuntimeClock::new()));
+        let mut wait = Box::pin(blocks::wait(stage_with_clock(&clock), Value::from(0.01)));
+
+        assert!(poll_once(&mut wait).is_pending());
+        clock.lock().unwrap().pause();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(poll_once(&mut wait).is_pending());
+
+        clock.lock().unwrap().resume();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(poll_once(&mut wait).is_ready());
+    }
+
+    #[test]
+    fn opaque_extent_leaves_out_transparent_padding() {
+        // A 4x4 image with one opaque pixel in the second column of the top row.
+        let mut image = image::RgbaImage::new(4, 4);
+        image.put_pixel(1, 0, image::Rgba([0, 0, 0, 255]));
+
+        assert_eq!(
+            Silhouette::new(&image).opaque_extent,
+            Some(Rectangle {
+                left: -0.25,
+                right: 0.0,
+                top: 0.5,
+                bottom: 0.25,
+            })
+        );
+        assert_eq!(
+            Silhouette::new(&image::RgbaImage::new(4, 4)).opaque_extent,
+            None
+        );
+    }
+
+    /// A model matrix that scales the unit square, rotates it by `degrees`
+    /// and moves it to `(x, y)`.
+    fn transform(scale: (f32, f32), degrees: f32, (x, y): (f32, f32)) -> [[f32; 4]; 4] {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        [
+            [scale.0 * cos, scale.0 * sin, 0.0, 0.0],
+            [-scale.1 * sin, scale.1 * cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [x, y, 0.0, 1.0],
+        ]
+    }
+
+    #[test]
+    fn inverse_transform_point_undoes_transform_point() {
+        let matrix = transform((96.0, 40.0), 30.0, (15.0, -40.0));
+
+        for point in [(0.0, 0.0), (0.5, 0.5), (-0.5, 0.25), (0.3, -0.45)] {
+            let (u, v) = inverse_transform_point(&matrix, transform_point(&matrix, point))
+                .expect("the matrix can be inverted");
+            assert!((u - point.0).abs() < 1e-5 && (v - point.1).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn flat_matrix_cannot_be_inverted() {
+        let matrix = transform((96.0, 0.0), 30.0, (15.0, -40.0));
+
+        assert_eq!(inverse_transform_point(&matrix, (15.0, -40.0)), None);
+    }
+
+    #[test]
+    fn silhouette_samples_the_unit_square() {
+        // A 2x2 image with a different color in each corner.
+        let image = image::RgbaImage::from_fn(2, 2, |x, y| {
+            image::Rgba([x as u8, y as u8, 0, (127 * (x + y)) as u8])
+        });
+        let silhouette = Silhouette::new(&image);
+
+        assert_eq!(silhouette.color_at((-0.25, 0.25)), Some([0, 0, 0, 0]));
+        assert_eq!(silhouette.color_at((0.25, 0.25)), Some([1, 0, 0, 127]));
+        assert_eq!(silhouette.color_at((-0.25, -0.25)), Some([0, 1, 0, 127]));
+        assert_eq!(silhouette.color_at((0.49, -0.49)), Some([1, 1, 0, 254]));
+        assert_eq!(silhouette.color_at((0.5, 0.0)), None);
+        assert!(!silhouette.is_opaque((-0.25, 0.25)));
+        assert!(silhouette.is_opaque((0.25, 0.25)));
+    }
+
+    #[test]
+    fn rectangle_points_cover_the_rectangle() {
+        let rectangle = Rectangle {
+            left: -0.5,
+            right: 1.5,
+            top: 1.0,
+            bottom: 0.2,
+        };
+        let points: Vec<_> = rectangle.points().collect();
+
+        assert_eq!(points.len(), 4 * 2);
+        assert_eq!(points.first(), Some(&(-1.0, 0.0)));
+        assert_eq!(points.last(), Some(&(2.0, 1.0)));
+    }
+
+    #[test]
+    fn hex_colors() {
+        for (hex, number) in [
+            ("#ff8800", Some(0xff8800)),
+            ("#f80", Some(0xff8800)),
+            ("#FfA", Some(0xffffaa)),
+            ("123456", Some(0x123456)),
+            ("#ff880", None),
+            ("#ff88000", None),
+            ("#gg8800", None),
+            ("#+12345", None),
+            ("", None),
+        ] {
+            assert_eq!(hex_color_to_number(hex), number, "{hex}");
+        }
+    }
+
+    #[test]
+    fn colors_to_rgb() {
+        for (color, rgb) in [
+            (Value::from("#f80"), [0xff, 0x88, 0x00]),
+            (Value::from("#nope"), [0, 0, 0]),
+            (Value::from(16711680.0), [0xff, 0x00, 0x00]),
+            (Value::from("65280"), [0x00, 0xff, 0x00]),
+            // Numbers wrap around to 32 bits, like in Scratch.
+            (Value::from(-1.0), [0xff, 0xff, 0xff]),
+            (Value::from(4294967296.0 + 16711680.0), [0xff, 0x00, 0x00]),
+            (Value::from(f32::NAN), [0, 0, 0]),
+        ] {
+            assert_eq!(color_to_rgb(&color), rgb, "{color:?}");
+        }
+    }
+
+    #[test]
+    fn color_matching_ignores_low_bits() {
+        // The low 3 bits of red and green and the low 4 bits of blue.
+        assert!(color_matches([0x87, 0x47, 0x2f], [0x80, 0x40, 0x20]));
+        assert!(!color_matches([0x88, 0x40, 0x20], [0x80, 0x40, 0x20]));
+        assert!(!color_matches([0x80, 0x48, 0x20], [0x80, 0x40, 0x20]));
+        assert!(!color_matches([0x80, 0x40, 0x30], [0x80, 0x40, 0x20]));
+
+        // The low 2 bits of each channel, and only opaque pixels.
+        assert!(mask_matches([0x83, 0x43, 0x23, 1], [0x80, 0x40, 0x20]));
+        assert!(!mask_matches([0x84, 0x40, 0x20, 255], [0x80, 0x40, 0x20]));
+        assert!(!mask_matches([0x80, 0x40, 0x20, 0], [0x80, 0x40, 0x20]));
+    }
+
+    #[test]
+    fn blended_colors() {
+        // Nothing shows the white stage.
+        assert_eq!(blend_colors([]), [255, 255, 255]);
+        // An opaque layer hides everything below it.
+        assert_eq!(
+            blend_colors([([10, 20, 30, 255], 1.0), ([200, 0, 0, 255], 1.0)]),
+            [10, 20, 30]
+        );
+        // A ghosted layer lets half of the stage through.
+        assert_eq!(blend_colors([([0, 0, 0, 255], 0.5)]), [128, 128, 128]);
+        // Half transparent red over opaque blue.
+        assert_eq!(
+            blend_colors([([255, 0, 0, 127], 1.0), ([0, 0, 255, 255], 1.0)]),
+            [127, 0, 128]
+        );
+    }
+
+    #[test]
+    fn scripts_do_not_move_a_dragged_sprite() {
+        let clock = Rc::new(Mutex::new(RuntimeClock::new_virtual()));
+        let stage = stage_with_clock(&clock);
+        let sprite = Rc::new(Mutex::new(SpriteBuilder::new("Sprite".into()).build()));
+        sprite.lock().unwrap().dragging = true;
+
+        blocks::go_to_xy(sprite.clone(), stage.clone(), 10.into(), 20.into());
+        blocks::set_x(sprite.clone(), stage.clone(), 30.into());
+        let position = |sprite: &Rc<Mutex<Sprite>>| {
+            let sprite = sprite.lock().unwrap();
+            (sprite.x, sprite.y)
+        };
+        assert_eq!(position(&sprite), (0.0, 0.0));
+
+        // The drag itself still moves it.
+        sprite.lock().unwrap().force_xy(10.0, 20.0, false);
+        assert_eq!(position(&sprite), (10.0, 20.0));
+
+        sprite.lock().unwrap().dragging = false;
+        blocks::set_x(sprite.clone(), stage.clone(), 30.into());
+        assert_eq!(sprite.lock().unwrap().x, 30.0);
+    }
+
+    #[test]
+    fn pinned_date() {
+        let clock = Rc::new(Mutex::new(RuntimeClock::new_virtual()));
+        let stage = stage_with_clock(&clock);
+        let date_of = |menu: &str| blocks::current_date(stage.clone(), Value::from(menu));
+
+        // A Friday afternoon, in a time zone ahead of UTC.
+        let date = DateTime::parse_from_rfc3339("2024-03-15T13:45:30+05:30").unwrap();
+        clock.lock().unwrap().pin_date(date);
+
+        assert_eq!(date_of("year"), Value::from(2024));
+        assert_eq!(date_of("month"), Value::from(3));
+        assert_eq!(date_of("date"), Value::from(15));
+        assert_eq!(date_of("dayofweek"), Value::from(6));
+        assert_eq!(date_of("hour"), Value::from(13));
+        assert_eq!(date_of("minute"), Value::from(45));
+        assert_eq!(date_of("second"), Value::from(30));
+
+        // Days since 2000 count from midnight UTC.
+        let date = DateTime::parse_from_rfc3339("2000-01-02T05:30:00+05:30").unwrap();
+        clock.lock().unwrap().pin_date(date);
+        assert_eq!(blocks::days_since_2000(stage.clone()), Value::from(1.0));
+
+        // Present-day dates keep the milliseconds, which are about 1e-8 days.
+        let date = DateTime::parse_from_rfc3339("2024-03-15T08:15:30.123Z").unwrap();
+        clock.lock().unwrap().pin_date(date);
+        let days: f64 = blocks::days_since_2000(stage.clone()).into();
+        assert!((days - (8840.0 + 29730.123 / 86400.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn numbers_from_32_bit_floats_keep_their_decimal_form() {
+        assert_eq!(Value::from(0.1f32).to_string(), "0.1");
+        assert_eq!(Value::from(-12.75f32).to_string(), "-12.75");
+        assert_eq!(
+            (Value::from(0.1f32) + Value::from(0.2f32)).to_string(),
+            "0.30000000000000004"
+        );
+    }
+
+    #[test]
+    fn questions_of_stopped_sprites_are_dropped() {
+        let clock = Rc::new(Mutex::new(RuntimeClock::new_virtual()));
+        let stage = stage_with_clock(&clock);
+        let first = Rc::new(Mutex::new(SpriteBuilder::new("First".into()).build()));
+        let second = Rc::new(Mutex::new(
+            SpriteBuilder::new("Second".into()).visible(false).build(),
+        ));
+
+        let mut first_ask = Box::pin(blocks::ask(
+            Some(first.clone()),
+            stage.clone(),
+            Value::from("one"),
+        ));
+        let mut second_ask = Box::pin(blocks::ask(
+            Some(second.clone()),
+            stage.clone(),
+            Value::from("two"),
+        ));
+        assert!(poll_once(&mut first_ask).is_pending());
+        assert!(poll_once(&mut second_ask).is_pending());
+        assert!(first.lock().unwrap().bubble.is_some());
+
+        // The second sprite is shown before its question comes up, so it asks
+        // in a speech bubble.
+        second.lock().unwrap().visible = true;
+        stage
+            .lock()
+            .unwrap()
+            .prompt
+            .clear_questions_of(Some(&first));
+        assert!(first.lock().unwrap().bubble.is_none());
+        assert!(second.lock().unwrap().bubble.is_some());
+        assert_eq!(stage.lock().unwrap().prompt.question(), "");
+        assert!(poll_once(&mut first_ask).is_ready());
+
+        stage
+            .lock()
+            .unwrap()
+            .prompt
+            .clear_questions_of(Some(&second));
+        assert!(!stage.lock().unwrap().prompt.is_active());
+        assert!(second.lock().unwrap().bubble.is_none());
+    }
+}